@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Result};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::RpcFilterType,
+    rpc_response::RpcKeyedAccount,
+};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    pubkey::Pubkey,
+};
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use super::error::AmmError;
+
+/// Source of on-chain accounts for building and updating Amms
+pub trait AccountProvider {
+    fn get_account(&self, key: &Pubkey) -> Result<Account>;
+    // Missing accounts are returned as None, in the order of `keys`
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+    // Accounts owned by `program_id` that pass every filter, as `getProgramAccounts`
+    fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: &[RpcFilterType],
+    ) -> Result<Vec<(Pubkey, Account)>>;
+}
+
+/// Reads accounts from a live cluster
+pub struct RpcAccountProvider {
+    pub client: RpcClient,
+}
+
+impl RpcAccountProvider {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: RpcClient::new(url.to_string()),
+        }
+    }
+}
+
+impl AccountProvider for RpcAccountProvider {
+    fn get_account(&self, key: &Pubkey) -> Result<Account> {
+        Ok(self.client.get_account(key)?)
+    }
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(self.client.get_multiple_accounts(keys)?)
+    }
+
+    fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: &[RpcFilterType],
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(filters.to_vec()),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            with_context: None,
+        };
+        Ok(self
+            .client
+            .get_program_accounts_with_config(program_id, config)?)
+    }
+}
+
+/// Serves accounts from memory, typically loaded from recorded JSON fixtures.
+///
+/// Fixtures are arrays of keyed accounts in the RPC `getProgramAccounts`
+/// format, with base64 encoded data.
+#[derive(Default, Clone)]
+pub struct MemoryAccountProvider {
+    pub accounts: HashMap<Pubkey, Account>,
+}
+
+impl MemoryAccountProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: Pubkey, account: Account) {
+        self.accounts.insert(key, account);
+    }
+
+    /// Snapshots `keys` from another provider, e.g. to record new fixtures from RPC
+    pub fn record(provider: &dyn AccountProvider, keys: &[Pubkey]) -> Result<Self> {
+        let mut memory_provider = Self::new();
+        for (key, account) in keys.iter().zip(provider.get_multiple_accounts(keys)?) {
+            let account = account.ok_or(AmmError::MissingAccount(*key))?;
+            memory_provider.insert(*key, account);
+        }
+        Ok(memory_provider)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let keyed_accounts: Vec<RpcKeyedAccount> = serde_json::from_str(json)?;
+        let mut memory_provider = Self::new();
+        for keyed_account in keyed_accounts {
+            let key = Pubkey::from_str(&keyed_account.pubkey)?;
+            let account = keyed_account
+                .account
+                .decode()
+                .ok_or_else(|| anyhow!("Cannot decode the data of account {}", key))?;
+            memory_provider.insert(key, account);
+        }
+        Ok(memory_provider)
+    }
+
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let mut keyed_accounts: Vec<RpcKeyedAccount> = self
+            .accounts
+            .iter()
+            .map(|(key, account)| RpcKeyedAccount {
+                pubkey: key.to_string(),
+                account: UiAccount::encode(key, account, UiAccountEncoding::Base64, None, None),
+            })
+            .collect();
+        keyed_accounts.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+        Ok(serde_json::to_string_pretty(&keyed_accounts)?)
+    }
+
+    pub fn to_json_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+impl AccountProvider for MemoryAccountProvider {
+    fn get_account(&self, key: &Pubkey) -> Result<Account> {
+        Ok(self
+            .accounts
+            .get(key)
+            .cloned()
+            .ok_or(AmmError::MissingAccount(*key))?)
+    }
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(keys
+            .iter()
+            .map(|key| self.accounts.get(key).cloned())
+            .collect())
+    }
+
+    fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: &[RpcFilterType],
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let mut program_accounts: Vec<(Pubkey, Account)> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| {
+                let account_shared_data = AccountSharedData::from((*account).clone());
+                account.owner == *program_id
+                    && filters
+                        .iter()
+                        .all(|filter| filter.allows(&account_shared_data))
+            })
+            .map(|(key, account)| (*key, account.clone()))
+            .collect();
+        // Same accounts, same order, whatever the insertion order
+        program_accounts.sort_by_key(|(key, _)| *key);
+        Ok(program_accounts)
+    }
+}
+
+#[test]
+fn test_memory_account_provider_json_round_trip() {
+    let mut memory_provider = MemoryAccountProvider::new();
+    let key = Pubkey::new_unique();
+    let account = Account {
+        lamports: 2_039_280,
+        data: vec![1, 2, 3, 255],
+        owner: Pubkey::new_unique(),
+        executable: false,
+        rent_epoch: 361,
+    };
+    memory_provider.insert(key, account.clone());
+
+    let json = memory_provider.to_json().unwrap();
+    let loaded = MemoryAccountProvider::from_json(&json).unwrap();
+    assert_eq!(loaded.get_account(&key).unwrap(), account);
+
+    let missing = Pubkey::new_unique();
+    assert_eq!(
+        loaded.get_multiple_accounts(&[missing, key]).unwrap(),
+        vec![None, Some(account)]
+    );
+    assert!(loaded.get_account(&missing).is_err());
+}
+
+#[test]
+fn test_memory_account_provider_get_program_accounts() {
+    use solana_client::rpc_filter::Memcmp;
+
+    let program_id = Pubkey::new_unique();
+    let mut memory_provider = MemoryAccountProvider::new();
+    let mut insert = |owner: Pubkey, data: Vec<u8>| {
+        let key = Pubkey::new_unique();
+        memory_provider.insert(
+            key,
+            Account {
+                lamports: 1,
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        key
+    };
+    let matching = insert(program_id, vec![7, 7, 1, 2]);
+    let wrong_prefix = insert(program_id, vec![7, 8, 1, 2]);
+    let wrong_size = insert(program_id, vec![7, 7, 1]);
+    insert(Pubkey::new_unique(), vec![7, 7, 1, 2]);
+
+    let filters = [
+        RpcFilterType::DataSize(4),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, vec![7, 7])),
+    ];
+    let keys = |accounts: Vec<(Pubkey, Account)>| -> Vec<Pubkey> {
+        accounts.into_iter().map(|(key, _)| key).collect()
+    };
+    assert_eq!(
+        keys(
+            memory_provider
+                .get_program_accounts(&program_id, &filters)
+                .unwrap()
+        ),
+        vec![matching]
+    );
+    let mut all = vec![matching, wrong_prefix, wrong_size];
+    all.sort();
+    assert_eq!(
+        keys(
+            memory_provider
+                .get_program_accounts(&program_id, &[])
+                .unwrap()
+        ),
+        all
+    );
+}
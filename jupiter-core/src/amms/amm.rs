@@ -1,3 +1,4 @@
+use crate::amms::error::AmmError;
 use anyhow::Result;
 use rust_decimal::Decimal;
 use std::collections::HashMap;
@@ -7,10 +8,21 @@ use solana_sdk::{account::Account, instruction::AccountMeta, pubkey::Pubkey};
 
 use jupiter::jupiter_override::SwapLeg;
 
+/// Which side of the trade `QuoteParams::amount` fixes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SwapMode {
+    /// `amount` is the input amount, quote the output
+    #[default]
+    ExactIn,
+    /// `amount` is the desired output amount, quote the input
+    ExactOut,
+}
+
 pub struct QuoteParams {
-    pub in_amount: u64,
+    pub amount: u64,
     pub input_mint: Pubkey,
     pub output_mint: Pubkey,
+    pub swap_mode: SwapMode,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -44,6 +56,16 @@ pub struct SwapLegAndAccountMetas {
     pub account_metas: Vec<AccountMeta>,
 }
 
+/// A reserve mint and the directions it can currently be swapped in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReserveMint {
+    pub mint: Pubkey,
+    // Can be the input mint of a swap
+    pub input: bool,
+    // Can be the output mint of a swap
+    pub output: bool,
+}
+
 /// Amm might trigger a setup step for the user
 #[derive(Clone)]
 pub enum AmmUserSetup {
@@ -57,18 +79,34 @@ pub trait Amm {
     fn key(&self) -> Pubkey;
     // Token mints that the amm supports for swapping
     fn get_reserve_mints(&self) -> Vec<Pubkey>;
+    // Reserve mints with the swap directions they currently allow
+    fn get_reserve_mints_with_direction(&self) -> Vec<ReserveMint> {
+        self.get_reserve_mints()
+            .into_iter()
+            .map(|mint| ReserveMint {
+                mint,
+                input: true,
+                output: true,
+            })
+            .collect()
+    }
     // Accounts related for quoting and creating ix
     fn get_accounts_to_update(&self) -> Vec<Pubkey>;
     // Picks data necessary to update it's internal state
     fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()>;
     // Returns quote for the given quote params
     fn quote(&self, quote_params: &QuoteParams) -> Result<Quote>;
+    // Quotes and applies the swap to the local state, as if it had executed,
+    // so that back-to-back routes see the liquidity it leaves
+    fn simulate_and_apply(&mut self, _quote_params: &QuoteParams) -> Result<Quote> {
+        Err(AmmError::NotSupported(format!("{} simulate_and_apply", self.label())).into())
+    }
 
     // Just state how do we make a swap instruction dont have to implement this
     fn get_swap_leg_and_account_metas(
         &self,
         swap_params: &SwapParams,
-    ) -> Result<bool>;
+    ) -> Result<SwapLegAndAccountMetas>;
 
     fn clone_amm(&self) -> Box<dyn Amm + Send + Sync>;
 
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    amms::{
+        amm::{Amm, KeyedAccount},
+        error::AmmError,
+        spl_token_swap_amm::{SplTokenSwapAmm, SPL_TOKEN_SWAP_PROGRAMS},
+    },
+    BalansolAmm,
+};
+
+/// Builds an Amm from a pool account, `KeyedAccount::params` included
+pub type AmmConstructor =
+    Box<dyn Fn(&KeyedAccount) -> Result<Box<dyn Amm + Send + Sync>> + Send + Sync>;
+
+/// Turns pool accounts into Amms by dispatching on the program owning them.
+/// `AmmFactory::default()` knows the Amms of this crate, other crates add
+/// theirs with `register`.
+pub struct AmmFactory {
+    constructors: HashMap<Pubkey, AmmConstructor>,
+}
+
+impl AmmFactory {
+    /// A factory without any program registered
+    pub fn new() -> Self {
+        Self {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// Registers the constructor of the pools owned by `program_id`, returning
+    /// the constructor it replaces
+    pub fn register<F>(&mut self, program_id: Pubkey, constructor: F) -> Option<AmmConstructor>
+    where
+        F: Fn(&KeyedAccount) -> Result<Box<dyn Amm + Send + Sync>> + Send + Sync + 'static,
+    {
+        self.constructors.insert(program_id, Box::new(constructor))
+    }
+
+    pub fn is_registered(&self, program_id: &Pubkey) -> bool {
+        self.constructors.contains_key(program_id)
+    }
+
+    pub fn program_ids(&self) -> Vec<Pubkey> {
+        self.constructors.keys().cloned().collect()
+    }
+
+    pub fn amm_from_keyed_account(
+        &self,
+        keyed_account: &KeyedAccount,
+    ) -> Result<Box<dyn Amm + Send + Sync>> {
+        let program_id = keyed_account.account.owner;
+        let constructor = self
+            .constructors
+            .get(&program_id)
+            .ok_or(AmmError::UnknownProgram(program_id))?;
+        constructor(keyed_account)
+    }
+}
+
+impl Default for AmmFactory {
+    fn default() -> Self {
+        let mut factory = Self::new();
+        factory.register(crate::ID, |keyed_account| {
+            Ok(Box::new(BalansolAmm::from_keyed_account(keyed_account)?))
+        });
+        for program_id in SPL_TOKEN_SWAP_PROGRAMS.keys() {
+            factory.register(*program_id, |keyed_account| {
+                Ok(Box::new(SplTokenSwapAmm::from_keyed_account(
+                    keyed_account,
+                )?))
+            });
+        }
+        factory
+    }
+}
+
+#[test]
+fn test_amm_factory_dispatches_on_owner() {
+    use crate::amms::spl_token_swap_amm::{mock_spl_token_swap, spl_token_swap_programs};
+    use crate::balansol_amm::{mock_keyed_account, mock_pool};
+    use spl_token_swap::curve::base::SwapCurve;
+
+    let factory = AmmFactory::default();
+    assert!(factory.is_registered(&crate::ID));
+    assert_eq!(
+        factory.program_ids().len(),
+        1 + SPL_TOKEN_SWAP_PROGRAMS.len()
+    );
+
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let mut keyed_account = mock_keyed_account(&pool);
+    keyed_account.account.owner = crate::ID;
+    keyed_account.params = Some(serde_json::json!({ "lpRouting": true }));
+    let amm = factory.amm_from_keyed_account(&keyed_account).unwrap();
+    assert_eq!(amm.label(), "Balansol");
+    assert_eq!(amm.key(), keyed_account.key);
+    // The params reach the constructor
+    assert!(amm.get_reserve_mints().contains(&pool.mint_lpt));
+
+    let (keyed_account, _, _) = mock_spl_token_swap(
+        spl_token_swap_programs::ORCA_V2,
+        SwapCurve::default(),
+        [1_000_000, 1_000_000],
+    );
+    let amm = factory.amm_from_keyed_account(&keyed_account).unwrap();
+    assert_eq!(amm.label(), "Orca V2");
+
+    // Constructor errors come through as is
+    let mut bad_keyed_account = keyed_account.clone();
+    bad_keyed_account.account.data.truncate(10);
+    assert!(factory.amm_from_keyed_account(&bad_keyed_account).is_err());
+
+    let mut unknown_keyed_account = keyed_account;
+    unknown_keyed_account.account.owner = Pubkey::new_unique();
+    let err = factory
+        .amm_from_keyed_account(&unknown_keyed_account)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<AmmError>(),
+        Some(AmmError::UnknownProgram(program_id)) if *program_id == unknown_keyed_account.account.owner
+    ));
+    assert!(err.to_string().contains("No Amm is registered for program"));
+}
+
+#[test]
+fn test_amm_factory_register() {
+    use crate::balansol_amm::{mock_keyed_account, mock_pool};
+
+    let mut factory = AmmFactory::new();
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let keyed_account = mock_keyed_account(&pool);
+    assert!(factory.amm_from_keyed_account(&keyed_account).is_err());
+
+    // A fork of Balansol deployed under another program id
+    let fork_program_id = keyed_account.account.owner;
+    assert!(factory
+        .register(fork_program_id, |keyed_account| {
+            Ok(Box::new(BalansolAmm::from_keyed_account(keyed_account)?))
+        })
+        .is_none());
+    assert!(factory.is_registered(&fork_program_id));
+    let amm = factory.amm_from_keyed_account(&keyed_account).unwrap();
+    assert_eq!(amm.get_reserve_mints(), pool.mints);
+
+    // Registering again replaces the constructor
+    assert!(factory
+        .register(fork_program_id, |_| Err(AmmError::NotSupported(
+            "Forks".to_string()
+        )
+        .into()))
+        .is_some());
+    assert!(factory.amm_from_keyed_account(&keyed_account).is_err());
+}
@@ -0,0 +1,28 @@
+use crate::errors::ErrorCode;
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+/// Failures of the off-chain Amm implementations, returned through `anyhow::Result`
+#[derive(Debug, Error)]
+pub enum AmmError {
+    #[error("Account {0} is missing")]
+    MissingAccount(Pubkey),
+    #[error("Account {0} has an unexpected discriminator")]
+    BadDiscriminator(Pubkey),
+    #[error("Pool {0} does not have one action, treasury, reserve and weight per mint")]
+    MalformedPool(Pubkey),
+    #[error("Mint {0} is not a reserve of the pool")]
+    UnknownMint(Pubkey),
+    #[error("Cannot swap mint {0} for itself")]
+    SameMint(Pubkey),
+    #[error("Math overflow: {0}")]
+    MathOverflow(ErrorCode),
+    #[error("Pool {0} is not tradable")]
+    PoolNotTradable(Pubkey),
+    #[error("Mint {0} cannot be traded in this direction")]
+    MintNotTradable(Pubkey),
+    #[error("{0} is not supported")]
+    NotSupported(String),
+    #[error("No Amm is registered for program {0}")]
+    UnknownProgram(Pubkey),
+}
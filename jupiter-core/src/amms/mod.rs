@@ -1,6 +1,9 @@
+pub mod account_provider;
 pub mod amm;
+pub mod amm_factory;
+pub mod error;
 
-mod spl_token_swap_amm;
+pub mod spl_token_swap_amm;
 
 #[cfg(test)]
-mod test_harness;
+pub(crate) mod test_harness;
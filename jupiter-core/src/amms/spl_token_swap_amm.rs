@@ -1,49 +1,775 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, convert::TryFrom};
 
+use anchor_lang::ToAccountMetas;
+use anyhow::Result;
+use jupiter::jupiter_override::{Swap, SwapLeg};
 use lazy_static::lazy_static;
-use solana_sdk::{pubkey, pubkey::Pubkey};
+use rust_decimal::Decimal;
+use solana_sdk::{program_pack::Pack, pubkey, pubkey::Pubkey};
+use spl_token_swap::{
+    curve::{base::SwapCurve, calculator::TradeDirection, fees::Fees},
+    state::SwapVersion,
+};
 
 use crate::{
-    amm::{Amm, QuoteParams},
-    BalansolAmm,
+    amms::{
+        amm::{
+            Amm, KeyedAccount, Quote, QuoteParams, SwapLegAndAccountMetas, SwapMode, SwapParams,
+        },
+        error::AmmError,
+    },
+    errors::ErrorCode,
 };
 
-mod spl_token_swap_programs {
+pub(crate) mod spl_token_swap_programs {
     use super::*;
-    pub const BALANSOL: Pubkey = pubkey!("6SRa2Kc3G4wTG319G4Se6yrRWeS1A1Hj79BC3o7X9v6T");
+    pub const SPL_TOKEN_SWAP: Pubkey = pubkey!("SwaPpA9LAaLfeLi3a68M4DjnLqgtticKg6CnyNwgAC8");
+    pub const ORCA_V1: Pubkey = pubkey!("DjVE6JNiYqPL2QXyCUUh8rNjHrbz9hXHNYt99MQ59qw1");
+    pub const ORCA_V2: Pubkey = pubkey!("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP");
+    pub const STEP: Pubkey = pubkey!("SSwpMgqNDsyV7mAgN9ady4bDVu5ySjmmXejXvy2vLt1");
 }
 
 lazy_static! {
+    /// Programs running the spl-token-swap state layout, with their labels.
+    /// Step has its own Jupiter swap, the others go through `tokenSwap`
     pub static ref SPL_TOKEN_SWAP_PROGRAMS: HashMap<Pubkey, String> = {
         let mut m = HashMap::new();
-        m.insert(spl_token_swap_programs::BALANSOL, "Balansol".into());
+        m.insert(spl_token_swap_programs::SPL_TOKEN_SWAP, "SPL Token Swap".into());
+        m.insert(spl_token_swap_programs::ORCA_V1, "Orca V1".into());
+        m.insert(spl_token_swap_programs::ORCA_V2, "Orca V2".into());
+        m.insert(spl_token_swap_programs::STEP, "Step".into());
         m
     };
 }
 
+/// `SwapCurve` is only `Clone` in spl-token-swap tests, share its calculator instead
+fn clone_swap_curve(swap_curve: &SwapCurve) -> SwapCurve {
+    SwapCurve {
+        curve_type: swap_curve.curve_type,
+        calculator: swap_curve.calculator.clone(),
+    }
+}
+
+/// Share of the source reserve used to measure the marginal price of a pool
+const SPOT_PRICE_PROBE_DIVISOR: u128 = 10_000;
+
+pub struct SplTokenSwapAmm {
+    key: Pubkey,
+    label: String,
+    program_id: Pubkey,
+    authority: Pubkey,
+    token_program_id: Pubkey,
+    // Token accounts holding the reserves, and their balances since the last update
+    token_a_account: Pubkey,
+    token_b_account: Pubkey,
+    reserves: [u128; 2],
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+    pool_mint: Pubkey,
+    pool_fee_account: Pubkey,
+    fees: Fees,
+    swap_curve: SwapCurve,
+}
+
+impl SplTokenSwapAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        let state = SwapVersion::unpack(&keyed_account.account.data)?;
+        let program_id = keyed_account.account.owner;
+        let label = SPL_TOKEN_SWAP_PROGRAMS
+            .get(&program_id)
+            .cloned()
+            .unwrap_or_else(|| "SPL Token Swap".to_string());
+        let authority = Pubkey::create_program_address(
+            &[&keyed_account.key.to_bytes(), &[state.bump_seed()]],
+            &program_id,
+        )?;
+
+        Ok(Self {
+            key: keyed_account.key,
+            label,
+            program_id,
+            authority,
+            token_program_id: *state.token_program_id(),
+            token_a_account: *state.token_a_account(),
+            token_b_account: *state.token_b_account(),
+            reserves: [0, 0],
+            token_a_mint: *state.token_a_mint(),
+            token_b_mint: *state.token_b_mint(),
+            pool_mint: *state.pool_mint(),
+            pool_fee_account: *state.pool_fee_account(),
+            fees: state.fees().clone(),
+            swap_curve: clone_swap_curve(state.swap_curve()),
+        })
+    }
+
+    /// Resolves the trade direction and the source and destination reserves
+    fn get_trade_direction(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+    ) -> Result<(TradeDirection, u128, u128)> {
+        if input_mint == output_mint {
+            return Err(AmmError::SameMint(input_mint).into());
+        }
+        for mint in [input_mint, output_mint] {
+            if mint != self.token_a_mint && mint != self.token_b_mint {
+                return Err(AmmError::UnknownMint(mint).into());
+            }
+        }
+        Ok(if input_mint == self.token_a_mint {
+            (TradeDirection::AtoB, self.reserves[0], self.reserves[1])
+        } else {
+            (TradeDirection::BtoA, self.reserves[1], self.reserves[0])
+        })
+    }
+
+    /// Output per unit of input of a small trade, without fees
+    fn get_spot_price(
+        &self,
+        trade_direction: TradeDirection,
+        source_reserve: u128,
+        destination_reserve: u128,
+    ) -> Option<Decimal> {
+        // An offset pool can start without any source token, so size the
+        // probe on the deeper side
+        let probe_amount =
+            (source_reserve.max(destination_reserve) / SPOT_PRICE_PROBE_DIVISOR).max(1);
+        let result = self.swap_curve.calculator.swap_without_fees(
+            probe_amount,
+            source_reserve,
+            destination_reserve,
+            trade_direction,
+        )?;
+        let destination_amount = u64::try_from(result.destination_amount_swapped).ok()?;
+        let source_amount = u64::try_from(result.source_amount_swapped).ok()?;
+        Decimal::from(destination_amount).checked_div(Decimal::from(source_amount))
+    }
+}
+
+impl Clone for SplTokenSwapAmm {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key,
+            label: self.label.clone(),
+            program_id: self.program_id,
+            authority: self.authority,
+            token_program_id: self.token_program_id,
+            token_a_account: self.token_a_account,
+            token_b_account: self.token_b_account,
+            reserves: self.reserves,
+            token_a_mint: self.token_a_mint,
+            token_b_mint: self.token_b_mint,
+            pool_mint: self.pool_mint,
+            pool_fee_account: self.pool_fee_account,
+            fees: self.fees.clone(),
+            swap_curve: clone_swap_curve(&self.swap_curve),
+        }
+    }
+}
+
+impl Amm for SplTokenSwapAmm {
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.token_a_mint, self.token_b_mint]
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![self.token_a_account, self.token_b_account]
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let mut reserves = [0; 2];
+        for (reserve, token_account) in reserves
+            .iter_mut()
+            .zip([self.token_a_account, self.token_b_account])
+        {
+            let data = accounts_map
+                .get(&token_account)
+                .ok_or(AmmError::MissingAccount(token_account))?;
+            *reserve = spl_token::state::Account::unpack(data)?.amount.into();
+        }
+        self.reserves = reserves;
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode,
+        } = quote_params;
+        if *swap_mode == SwapMode::ExactOut {
+            // The swap instruction only takes an input amount
+            return Err(AmmError::NotSupported("Exact out token swaps".to_string()).into());
+        }
+        let (trade_direction, source_reserve, destination_reserve) =
+            self.get_trade_direction(*input_mint, *output_mint)?;
+        // Offset pools sell the offset token against a virtual reserve, only
+        // an empty destination cannot pay out
+        if destination_reserve == 0 {
+            return Err(AmmError::PoolNotTradable(self.key).into());
+        }
+
+        let swap_result = match self.swap_curve.swap(
+            (*amount).into(),
+            source_reserve,
+            destination_reserve,
+            trade_direction,
+            &self.fees,
+        ) {
+            Some(swap_result) => swap_result,
+            // Nothing comes out, e.g. an amount smaller than the fees, or
+            // more than the destination reserve would
+            None => {
+                let not_enough_liquidity = self
+                    .swap_curve
+                    .calculator
+                    .swap_without_fees(
+                        (*amount).into(),
+                        source_reserve,
+                        destination_reserve,
+                        trade_direction,
+                    )
+                    .is_some_and(|result| result.destination_amount_swapped >= destination_reserve);
+                return Ok(Quote {
+                    not_enough_liquidity,
+                    in_amount: *amount,
+                    fee_mint: *input_mint,
+                    ..Quote::default()
+                });
+            }
+        };
+
+        let fee_amount = swap_result
+            .trade_fee
+            .checked_add(swap_result.owner_fee)
+            .and_then(|fee_amount| u64::try_from(fee_amount).ok())
+            .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
+        let out_amount = u64::try_from(swap_result.destination_amount_swapped)
+            .map_err(|_| AmmError::MathOverflow(ErrorCode::Overflow))?;
+        let fee_pct = if *amount == 0 {
+            Decimal::ZERO
+        } else {
+            Decimal::from(fee_amount) / Decimal::from(*amount)
+        };
+
+        // Compare the rate paid on the amount left after fees against the marginal rate
+        let swapped_amount = amount.saturating_sub(fee_amount);
+        let price_impact_pct =
+            match self.get_spot_price(trade_direction, source_reserve, destination_reserve) {
+                Some(spot_price) if swapped_amount > 0 && !spot_price.is_zero() => {
+                    let effective_price = Decimal::from(out_amount) / Decimal::from(swapped_amount);
+                    (Decimal::ONE - effective_price / spot_price).max(Decimal::ZERO)
+                }
+                _ => Decimal::ZERO,
+            };
+
+        Ok(Quote {
+            not_enough_liquidity: swap_result.new_swap_destination_amount == 0,
+            min_out_amount: Some(out_amount),
+            in_amount: *amount,
+            out_amount,
+            fee_amount,
+            fee_mint: *input_mint,
+            fee_pct,
+            price_impact_pct,
+            ..Quote::default()
+        })
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            destination_mint,
+            user_source_token_account,
+            user_destination_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+        let (trade_direction, _, _) = self.get_trade_direction(*source_mint, *destination_mint)?;
+        let (swap_source, swap_destination) = match trade_direction {
+            TradeDirection::AtoB => (self.token_a_account, self.token_b_account),
+            TradeDirection::BtoA => (self.token_b_account, self.token_a_account),
+        };
+
+        let token_swap = jupiter::accounts::TokenSwap {
+            token_swap_program: self.program_id,
+            token_program: self.token_program_id,
+            swap: self.key,
+            authority: self.authority,
+            user_transfer_authority: *user_transfer_authority,
+            source: *user_source_token_account,
+            swap_source,
+            swap_destination,
+            destination: *user_destination_token_account,
+            pool_mint: self.pool_mint,
+            pool_fee: self.pool_fee_account,
+        };
+        let (swap, account_metas) = if self.program_id == spl_token_swap_programs::STEP {
+            let step_swap = jupiter::accounts::StepSwap {
+                token_swap_program: token_swap.token_swap_program,
+                token_program: token_swap.token_program,
+                swap: token_swap.swap,
+                authority: token_swap.authority,
+                user_transfer_authority: token_swap.user_transfer_authority,
+                source: token_swap.source,
+                swap_source: token_swap.swap_source,
+                swap_destination: token_swap.swap_destination,
+                destination: token_swap.destination,
+                pool_mint: token_swap.pool_mint,
+                pool_fee: token_swap.pool_fee,
+            };
+            (Swap::Step, step_swap.to_account_metas(None))
+        } else {
+            (Swap::TokenSwap, token_swap.to_account_metas(None))
+        };
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+fn mock_fees() -> Fees {
+    Fees {
+        trade_fee_numerator: 25,
+        trade_fee_denominator: 10_000,
+        owner_trade_fee_numerator: 5,
+        owner_trade_fee_denominator: 10_000,
+        owner_withdraw_fee_numerator: 0,
+        owner_withdraw_fee_denominator: 0,
+        host_fee_numerator: 20,
+        host_fee_denominator: 100,
+    }
+}
+
+/// A pool of `program_id` with the given curve, and the accounts to update it
+#[cfg(test)]
+pub(crate) fn mock_spl_token_swap(
+    program_id: Pubkey,
+    swap_curve: SwapCurve,
+    reserves: [u64; 2],
+) -> (
+    KeyedAccount,
+    spl_token_swap::state::SwapV1,
+    HashMap<Pubkey, Vec<u8>>,
+) {
+    use solana_sdk::account::Account;
+    use spl_token_swap::state::SwapV1;
+
+    let key = Pubkey::new_unique();
+    let (_, bump_seed) = Pubkey::find_program_address(&[&key.to_bytes()], &program_id);
+    let swap_v1 = SwapV1 {
+        is_initialized: true,
+        bump_seed,
+        token_program_id: spl_token::ID,
+        token_a: Pubkey::new_unique(),
+        token_b: Pubkey::new_unique(),
+        pool_mint: Pubkey::new_unique(),
+        token_a_mint: Pubkey::new_unique(),
+        token_b_mint: Pubkey::new_unique(),
+        pool_fee_account: Pubkey::new_unique(),
+        fees: mock_fees(),
+        swap_curve: clone_swap_curve(&swap_curve),
+    };
+    let mut data = vec![0; SwapVersion::LATEST_LEN];
+    SwapVersion::pack(SwapVersion::SwapV1(clone_swap_v1(&swap_v1)), &mut data).unwrap();
+
+    let mut accounts_map = HashMap::new();
+    for (token_account, mint, amount) in [
+        (swap_v1.token_a, swap_v1.token_a_mint, reserves[0]),
+        (swap_v1.token_b, swap_v1.token_b_mint, reserves[1]),
+    ] {
+        let mut token_account_data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..spl_token::state::Account::default()
+        }
+        .pack_into_slice(&mut token_account_data);
+        accounts_map.insert(token_account, token_account_data);
+    }
+
+    let keyed_account = KeyedAccount {
+        key,
+        account: Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+        params: None,
+    };
+    (keyed_account, swap_v1, accounts_map)
+}
+
+#[cfg(test)]
+fn clone_swap_v1(swap_v1: &spl_token_swap::state::SwapV1) -> spl_token_swap::state::SwapV1 {
+    spl_token_swap::state::SwapV1 {
+        fees: swap_v1.fees.clone(),
+        swap_curve: clone_swap_curve(&swap_v1.swap_curve),
+        ..*swap_v1
+    }
+}
+
 #[test]
-fn test_new_spl_token_swap() -> Result<(), String> {
-    use crate::amms::test_harness::AmmTestHarness;
+fn test_quote_all_curve_types() {
+    use spl_token_swap::curve::{
+        base::CurveType, constant_price::ConstantPriceCurve,
+        constant_product::ConstantProductCurve, offset::OffsetCurve, stable::StableCurve,
+    };
+    use std::sync::Arc;
 
-    // Devnet
-    pub const USDC_MINT: Pubkey = pubkey!("2z6Ci38Cx6PyL3tFrT95vbEeB3izqpoLdxxBkJk2euyj");
-    pub const SNTR_MINT: Pubkey = pubkey!("5YwUkPdXLoujGkZuo9B4LsLKj3hdkDcfP4derpspifSJ");
-    const USDC_SNTR_POOL: Pubkey = pubkey!("HVBPjtbK4Hrk7DHj1GjaFwprj5oLmQKoGMBti1rZQKuo");
+    let curves = vec![
+        SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Arc::new(ConstantProductCurve {}),
+        },
+        SwapCurve {
+            curve_type: CurveType::ConstantPrice,
+            calculator: Arc::new(ConstantPriceCurve { token_b_price: 2 }),
+        },
+        SwapCurve {
+            curve_type: CurveType::Stable,
+            calculator: Arc::new(StableCurve { amp: 100 }),
+        },
+        SwapCurve {
+            curve_type: CurveType::Offset,
+            calculator: Arc::new(OffsetCurve {
+                token_b_offset: 500_000_000_000,
+            }),
+        },
+    ];
+    let reserves = [1_000_000_000_000, 2_000_000_000_000];
+    for swap_curve in curves {
+        let curve_type = swap_curve.curve_type;
+        let (keyed_account, swap_v1, accounts_map) =
+            mock_spl_token_swap(spl_token_swap_programs::ORCA_V2, swap_curve, reserves);
+        let mut amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+        assert_eq!(amm.label(), "Orca V2");
+        assert_eq!(
+            amm.get_reserve_mints(),
+            vec![swap_v1.token_a_mint, swap_v1.token_b_mint]
+        );
+        amm.update(&accounts_map).unwrap();
 
-    let test_harness = AmmTestHarness::new();
+        for (input_mint, output_mint, trade_direction, source_reserve, destination_reserve) in [
+            (
+                swap_v1.token_a_mint,
+                swap_v1.token_b_mint,
+                TradeDirection::AtoB,
+                reserves[0],
+                reserves[1],
+            ),
+            (
+                swap_v1.token_b_mint,
+                swap_v1.token_a_mint,
+                TradeDirection::BtoA,
+                reserves[1],
+                reserves[0],
+            ),
+        ] {
+            let quote = |amount| {
+                amm.quote(&QuoteParams {
+                    amount,
+                    input_mint,
+                    output_mint,
+                    swap_mode: SwapMode::ExactIn,
+                })
+                .unwrap()
+            };
+            // Same result as the program's own curve math
+            let expected = swap_v1
+                .swap_curve
+                .swap(
+                    1_000_000_000,
+                    source_reserve.into(),
+                    destination_reserve.into(),
+                    trade_direction,
+                    &swap_v1.fees,
+                )
+                .unwrap();
+            let small_quote = quote(1_000_000_000);
+            assert_eq!(
+                u128::from(small_quote.out_amount),
+                expected.destination_amount_swapped
+            );
+            assert_eq!(
+                u128::from(small_quote.fee_amount),
+                expected.trade_fee + expected.owner_fee
+            );
+            assert_eq!(small_quote.fee_mint, input_mint);
+            assert_eq!(small_quote.fee_pct, Decimal::new(3, 3));
+            assert!(!small_quote.not_enough_liquidity);
 
-    let keyed_account = test_harness.get_keyed_account(USDC_SNTR_POOL).unwrap();
-    let amm = BalansolAmm::from_keyed_account(&keyed_account).unwrap();
+            let large_quote = quote(200_000_000_000);
+            assert!(large_quote.out_amount > small_quote.out_amount);
+            assert!(
+                small_quote.price_impact_pct < Decimal::new(1, 3),
+                "{:?}",
+                curve_type
+            );
+            if curve_type == CurveType::ConstantPrice {
+                assert!(large_quote.price_impact_pct < Decimal::new(1, 3));
+            } else {
+                assert!(
+                    large_quote.price_impact_pct > small_quote.price_impact_pct,
+                    "{:?}",
+                    curve_type
+                );
+            }
+        }
+    }
 
-    let quote = amm
-        .quote(&QuoteParams {
-            input_mint: USDC_MINT,
-            in_amount: 1000000000, // 1USDC
-            output_mint: SNTR_MINT,
+    // A fresh offset pool holds no token B, users buy A with B against the offset
+    let token_b_offset = 500_000_000_000;
+    let (keyed_account, swap_v1, accounts_map) = mock_spl_token_swap(
+        spl_token_swap_programs::ORCA_V2,
+        SwapCurve {
+            curve_type: CurveType::Offset,
+            calculator: Arc::new(OffsetCurve { token_b_offset }),
+        },
+        [reserves[0], 0],
+    );
+    let mut amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+    amm.update(&accounts_map).unwrap();
+    let quote = |amount, input_mint, output_mint| {
+        amm.quote(&QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode: SwapMode::ExactIn,
         })
+    };
+    let expected = swap_v1
+        .swap_curve
+        .swap(
+            1_000_000_000,
+            0,
+            reserves[0].into(),
+            TradeDirection::BtoA,
+            &swap_v1.fees,
+        )
         .unwrap();
+    let small_quote = quote(1_000_000_000, swap_v1.token_b_mint, swap_v1.token_a_mint).unwrap();
+    assert_eq!(
+        u128::from(small_quote.out_amount),
+        expected.destination_amount_swapped
+    );
+    assert!(!small_quote.not_enough_liquidity);
+    // 0.2% of the virtual B reserve
+    assert!(small_quote.price_impact_pct > Decimal::new(1, 3));
+    assert!(small_quote.price_impact_pct < Decimal::new(3, 3));
+    let large_quote = quote(200_000_000_000, swap_v1.token_b_mint, swap_v1.token_a_mint).unwrap();
+    assert!(large_quote.price_impact_pct > Decimal::new(1, 1));
+    // There is no B to buy yet
+    let err = quote(1_000_000_000, swap_v1.token_a_mint, swap_v1.token_b_mint).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AmmError>(),
+        Some(AmmError::PoolNotTradable(key)) if *key == keyed_account.key
+    ));
+}
+
+#[test]
+fn test_spl_token_swap_errors() {
+    use spl_token_swap::curve::{base::CurveType, constant_price::ConstantPriceCurve};
+    use std::sync::Arc;
+
+    let (keyed_account, swap_v1, accounts_map) = mock_spl_token_swap(
+        spl_token_swap_programs::SPL_TOKEN_SWAP,
+        SwapCurve {
+            curve_type: CurveType::ConstantPrice,
+            calculator: Arc::new(ConstantPriceCurve { token_b_price: 2 }),
+        },
+        [1_000_000, 1_000_000],
+    );
+    let mut amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+    assert_eq!(amm.label(), "SPL Token Swap");
+    assert_eq!(
+        amm.get_accounts_to_update(),
+        vec![swap_v1.token_a, swap_v1.token_b]
+    );
+    let err = amm.update(&HashMap::new()).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref::<AmmError>(), Some(AmmError::MissingAccount(key)) if *key == swap_v1.token_a)
+    );
+    amm.update(&accounts_map).unwrap();
+
+    let quote = |amount, input_mint, output_mint, swap_mode| {
+        amm.quote(&QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode,
+        })
+    };
+    let err = quote(
+        1_000,
+        swap_v1.token_a_mint,
+        swap_v1.token_b_mint,
+        SwapMode::ExactOut,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AmmError>(),
+        Some(AmmError::NotSupported(_))
+    ));
+    let unknown_mint = Pubkey::new_unique();
+    let err = quote(1_000, swap_v1.token_a_mint, unknown_mint, SwapMode::ExactIn).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref::<AmmError>(), Some(AmmError::UnknownMint(mint)) if *mint == unknown_mint)
+    );
+    let err = quote(
+        1_000,
+        swap_v1.token_a_mint,
+        swap_v1.token_a_mint,
+        SwapMode::ExactIn,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AmmError>(),
+        Some(AmmError::SameMint(_))
+    ));
+    // Too small to pay the fees and get anything out
+    let dust_quote = quote(
+        1,
+        swap_v1.token_a_mint,
+        swap_v1.token_b_mint,
+        SwapMode::ExactIn,
+    )
+    .unwrap();
+    assert_eq!(dust_quote.out_amount, 0);
+    assert!(!dust_quote.not_enough_liquidity);
+    // A constant price pays out regardless of the reserve left
+    let drain_quote = quote(
+        600_000,
+        swap_v1.token_b_mint,
+        swap_v1.token_a_mint,
+        SwapMode::ExactIn,
+    )
+    .unwrap();
+    assert_eq!(drain_quote.out_amount, 0);
+    assert!(drain_quote.not_enough_liquidity);
+
+    let mut bad_keyed_account = keyed_account;
+    bad_keyed_account.account.data.truncate(10);
+    assert!(SplTokenSwapAmm::from_keyed_account(&bad_keyed_account).is_err());
+}
+
+#[test]
+fn test_token_swap_leg_account_metas_match_idl() {
+    use serde_json::Value;
+
+    let idl: Value = serde_json::from_str(include_str!("../../../jupiter/idl.json")).unwrap();
+    for (program_id, idl_instruction) in [
+        (spl_token_swap_programs::SPL_TOKEN_SWAP, "tokenSwap"),
+        (spl_token_swap_programs::ORCA_V2, "tokenSwap"),
+        (spl_token_swap_programs::STEP, "stepSwap"),
+    ] {
+        check_token_swap_leg(&idl, program_id, idl_instruction);
+    }
+}
+
+#[cfg(test)]
+fn check_token_swap_leg(idl: &serde_json::Value, program_id: Pubkey, idl_instruction: &str) {
+    use serde_json::Value;
+
+    let (keyed_account, swap_v1, _) =
+        mock_spl_token_swap(program_id, SwapCurve::default(), [1_000_000, 1_000_000]);
+    let amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+    let user = Pubkey::new_unique();
+    let user_source_token_account = Pubkey::new_unique();
+    let user_destination_token_account = Pubkey::new_unique();
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: swap_v1.token_b_mint,
+            destination_mint: swap_v1.token_a_mint,
+            user_source_token_account,
+            user_destination_token_account,
+            user_transfer_authority: user,
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000,
+        })
+        .unwrap();
+    let swap = match swap_leg_and_account_metas.swap_leg {
+        SwapLeg::Swap { swap } => swap,
+        _ => panic!("{} is not a single swap leg", program_id),
+    };
+    match idl_instruction {
+        "stepSwap" => assert!(matches!(swap, Swap::Step)),
+        _ => assert!(matches!(swap, Swap::TokenSwap)),
+    }
 
-    println!("Quote result: {:?}", quote);
+    let idl_accounts = idl["instructions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|ix| ix["name"] == idl_instruction)
+        .unwrap()["accounts"]
+        .as_array()
+        .unwrap()
+        .clone();
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), idl_accounts.len());
 
-    Err("STOP".to_string())
+    let authority = Pubkey::create_program_address(
+        &[&keyed_account.key.to_bytes(), &[swap_v1.bump_seed]],
+        &program_id,
+    )
+    .unwrap();
+    // Selling token B reads from the B reserve and pays out of the A reserve
+    let expected_keys = [
+        ("tokenSwapProgram", program_id),
+        ("tokenProgram", spl_token::ID),
+        ("swap", keyed_account.key),
+        ("authority", authority),
+        ("userTransferAuthority", user),
+        ("source", user_source_token_account),
+        ("swapSource", swap_v1.token_b),
+        ("swapDestination", swap_v1.token_a),
+        ("destination", user_destination_token_account),
+        ("poolMint", swap_v1.pool_mint),
+        ("poolFee", swap_v1.pool_fee_account),
+    ];
+    for ((meta, idl_account), (name, key)) in account_metas
+        .iter()
+        .zip(idl_accounts.iter())
+        .zip(expected_keys.iter())
+    {
+        assert_eq!(idl_account["name"], *name);
+        assert_eq!(meta.pubkey, *key, "{}", name);
+        assert_eq!(
+            Value::Bool(meta.is_writable),
+            idl_account["isMut"],
+            "{}",
+            name
+        );
+        assert_eq!(
+            Value::Bool(meta.is_signer),
+            idl_account["isSigner"],
+            "{}",
+            name
+        );
+    }
 }
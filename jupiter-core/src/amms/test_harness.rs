@@ -1,25 +1,45 @@
 use anyhow::Result;
-use solana_client::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
-use std::collections::HashMap;
+use std::{collections::HashMap, fs};
 
 use crate::config;
 
-use super::amm::{Amm, KeyedAccount};
+use super::{
+    account_provider::{AccountProvider, MemoryAccountProvider, RpcAccountProvider},
+    amm::{Amm, KeyedAccount},
+};
+
+pub const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
 
 pub struct AmmTestHarness {
-    pub client: RpcClient,
+    pub provider: Box<dyn AccountProvider>,
 }
 
 impl AmmTestHarness {
+    /// Serves the accounts recorded in `tests/fixtures`, no network needed
     pub fn new() -> Self {
-        Self {
-            client: RpcClient::new(config::RPC_URL),
+        let mut provider = MemoryAccountProvider::new();
+        for entry in fs::read_dir(FIXTURES_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                let fixture = MemoryAccountProvider::from_json_file(&path).unwrap();
+                provider.accounts.extend(fixture.accounts);
+            }
         }
+        Self::new_with_provider(Box::new(provider))
+    }
+
+    /// Reads live accounts from `config::RPC_URL`
+    pub fn new_with_rpc() -> Self {
+        Self::new_with_provider(Box::new(RpcAccountProvider::new(config::RPC_URL)))
+    }
+
+    pub fn new_with_provider(provider: Box<dyn AccountProvider>) -> Self {
+        Self { provider }
     }
 
     pub fn get_keyed_account(&self, key: Pubkey) -> Result<KeyedAccount> {
-        let account = self.client.get_account(&key)?;
+        let account = self.provider.get_account(&key)?;
         Ok(KeyedAccount {
             key,
             account,
@@ -29,10 +49,9 @@ impl AmmTestHarness {
 
     pub fn update_amm(&self, amm: &mut dyn Amm) {
         let accounts_to_update = amm.get_accounts_to_update();
-        println!("{:?}", accounts_to_update);
 
         let accounts_map = self
-            .client
+            .provider
             .get_multiple_accounts(&accounts_to_update)
             .unwrap()
             .iter()
@@ -46,3 +65,20 @@ impl AmmTestHarness {
         amm.update(&accounts_map).unwrap();
     }
 }
+
+#[test]
+#[ignore = "needs access to config::RPC_URL"]
+fn refresh_fixtures() {
+    let rpc_harness = AmmTestHarness::new_with_rpc();
+    for entry in fs::read_dir(FIXTURES_DIR).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            let fixture = MemoryAccountProvider::from_json_file(&path).unwrap();
+            let keys: Vec<Pubkey> = fixture.accounts.keys().cloned().collect();
+            MemoryAccountProvider::record(rpc_harness.provider.as_ref(), &keys)
+                .unwrap()
+                .to_json_file(&path)
+                .unwrap();
+        }
+    }
+}
@@ -1,29 +1,291 @@
 use crate::constant::*;
+use crate::errors::ErrorCode;
+use crate::fixed_point::{self, ONE};
+use crate::lbp_pool::LbpPool;
 use crate::pool::Pool;
 use crate::{
-    amms::amm::{Amm, KeyedAccount, Quote, QuoteParams, SwapParams},
-    Exchange,
+    amms::{
+        amm::{
+            Amm, KeyedAccount, Quote, QuoteParams, ReserveMint, SwapLegAndAccountMetas, SwapMode,
+            SwapParams,
+        },
+        error::AmmError,
+    },
+    Accessor, Exchange, Liquidity, Operation, PoolState,
 };
-use anchor_lang::prelude::*;
+use anchor_lang::{
+    prelude::*,
+    solana_program::{clock::Clock, sysvar},
+    Discriminator,
+};
+use anchor_spl::{associated_token, token};
 use anyhow::{Ok, Result};
+use jupiter::jupiter_override::{Swap, SwapLeg};
 use num_traits::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use solana_sdk::program_pack::Pack;
 use std::collections::HashMap;
 
+fn fixed_to_decimal(value: u128) -> Decimal {
+    Decimal::from_i128_with_scale(value as i128, 18)
+}
+
+/// Options read from `KeyedAccount::params`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BalansolParams {
+    // Also list the LP mint, quoting deposits and withdrawals as swaps
+    #[serde(default)]
+    lp_routing: bool,
+    // Largest swaps as a share of the reserves, in PRECISION
+    #[serde(default = "default_max_in_ratio")]
+    max_in_ratio: u64,
+    #[serde(default = "default_max_out_ratio")]
+    max_out_ratio: u64,
+}
+
+impl Default for BalansolParams {
+    fn default() -> Self {
+        Self {
+            lp_routing: false,
+            max_in_ratio: MAX_IN_RATIO,
+            max_out_ratio: MAX_OUT_RATIO,
+        }
+    }
+}
+
+fn default_max_in_ratio() -> u64 {
+    MAX_IN_RATIO
+}
+
+fn default_max_out_ratio() -> u64 {
+    MAX_OUT_RATIO
+}
+
 pub struct BalansolAmm {
     key: Pubkey,
     label: String,
     program_id: Pubkey,
+    // Static pools, or the snapshot of `lbp_pool` at `unix_timestamp`
     pool: Pool,
+    lbp_pool: Option<LbpPool>,
+    unix_timestamp: i64,
+    lp_routing: bool,
+    // Supply of `pool.mint_lpt`, only fetched with LP routing
+    lpt_supply: u64,
+    max_in_ratio: u64,
+    max_out_ratio: u64,
 }
 
 impl BalansolAmm {
     pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
-        let pool: Pool = Pool::try_deserialize(&mut keyed_account.account.data.as_ref()).unwrap();
+        let (pool, lbp_pool) =
+            Self::deserialize_pool(keyed_account.key, &keyed_account.account.data, 0)?;
+        let params: BalansolParams = match &keyed_account.params {
+            Some(params) => serde_json::from_value(params.clone())?,
+            None => BalansolParams::default(),
+        };
         Ok(Self {
             key: keyed_account.key,
             label: "Balansol".to_string(),
             program_id: keyed_account.account.owner,
             pool,
+            lbp_pool,
+            unix_timestamp: 0,
+            lp_routing: params.lp_routing,
+            lpt_supply: 0,
+            max_in_ratio: params.max_in_ratio,
+            max_out_ratio: params.max_out_ratio,
+        })
+    }
+
+    /// Reads a `Pool`, or an `LbpPool` along with its snapshot at `unix_timestamp`
+    fn deserialize_pool(
+        key: Pubkey,
+        mut data: &[u8],
+        unix_timestamp: i64,
+    ) -> Result<(Pool, Option<LbpPool>)> {
+        if data.len() < ACCOUNT_DISCRIMINATOR {
+            return Err(AmmError::BadDiscriminator(key).into());
+        }
+        let discriminator = &data[..ACCOUNT_DISCRIMINATOR];
+        let (pool, lbp_pool) = if discriminator == Pool::discriminator() {
+            (Pool::try_deserialize(&mut data)?, None)
+        } else if discriminator == LbpPool::discriminator() {
+            let lbp_pool = LbpPool::try_deserialize(&mut data)?;
+            if lbp_pool.start_weights.len() != lbp_pool.mints.len()
+                || lbp_pool.end_weights.len() != lbp_pool.mints.len()
+            {
+                return Err(AmmError::MalformedPool(key).into());
+            }
+            let pool = lbp_pool
+                .to_pool(unix_timestamp)
+                .ok_or(AmmError::MathOverflow(ErrorCode::ParamsWeights))?;
+            (pool, Some(lbp_pool))
+        } else {
+            return Err(AmmError::BadDiscriminator(key).into());
+        };
+        // Every per-mint vector is indexed by the mint position
+        let mint_count = pool.mints.len();
+        if [
+            pool.actions.len(),
+            pool.treasuries.len(),
+            pool.reserves.len(),
+            pool.weights.len(),
+        ]
+        .iter()
+        .any(|len| *len != mint_count)
+        {
+            return Err(AmmError::MalformedPool(key).into());
+        }
+        Ok((pool, lbp_pool))
+    }
+
+    /// Quotes against the weights at `unix_timestamp` rather than at the last
+    /// update, e.g. the expected execution time of a liquidity bootstrapping pool
+    pub fn quote_at(&self, quote_params: &QuoteParams, unix_timestamp: i64) -> Result<Quote> {
+        match &self.lbp_pool {
+            Some(lbp_pool) => {
+                let mut amm = self.clone();
+                amm.pool = lbp_pool
+                    .to_pool(unix_timestamp)
+                    .ok_or(AmmError::MathOverflow(ErrorCode::ParamsWeights))?;
+                amm.unix_timestamp = unix_timestamp;
+                amm.quote(quote_params)
+            }
+            None => self.quote(quote_params),
+        }
+    }
+
+    /// Resolves the bid and ask indexes of a swap between two pool mints,
+    /// checking that the pool and both mints allow it
+    fn get_swap_mint_indexes(&self, bid_mint: Pubkey, ask_mint: Pubkey) -> Result<(usize, usize)> {
+        if bid_mint == ask_mint {
+            return Err(AmmError::SameMint(bid_mint).into());
+        }
+        let bid_mint_idx = self
+            .pool
+            .get_mint_index(bid_mint)
+            .ok_or(AmmError::UnknownMint(bid_mint))?;
+        let ask_mint_idx = self
+            .pool
+            .get_mint_index(ask_mint)
+            .ok_or(AmmError::UnknownMint(ask_mint))?;
+
+        // Same checks as the on-chain swap, which fails with PoolStopped or MintState
+        if !self.pool.valid_pool_states(vec![PoolState::Initialized]) {
+            return Err(AmmError::PoolNotTradable(self.key).into());
+        }
+        if !self.pool.can_bid(bid_mint_idx) {
+            return Err(AmmError::MintNotTradable(bid_mint).into());
+        }
+        if !self.pool.can_ask(ask_mint_idx) {
+            return Err(AmmError::MintNotTradable(ask_mint).into());
+        }
+        Ok((bid_mint_idx, ask_mint_idx))
+    }
+
+    fn is_lp_swap(&self, input_mint: Pubkey, output_mint: Pubkey) -> bool {
+        self.lp_routing && (input_mint == self.pool.mint_lpt || output_mint == self.pool.mint_lpt)
+    }
+
+    /// Quotes a deposit (mint to LPT) or a withdrawal (LPT to mint) as a swap
+    fn quote_lp(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode,
+        } = quote_params;
+        let pool = &self.pool;
+
+        if input_mint == output_mint {
+            return Err(AmmError::SameMint(*input_mint).into());
+        }
+        if *swap_mode == SwapMode::ExactOut {
+            return Err(AmmError::NotSupported("Exact out LP swaps".to_string()).into());
+        }
+        let is_deposit = *output_mint == pool.mint_lpt;
+        let mint = if is_deposit { input_mint } else { output_mint };
+        let mint_idx = pool
+            .get_mint_index(*mint)
+            .ok_or(AmmError::UnknownMint(*mint))?;
+        if !pool.valid_pool_states(vec![PoolState::Initialized])
+            || self.lpt_supply == 0
+            || pool.reserves[mint_idx] == 0
+        {
+            return Err(AmmError::PoolNotTradable(self.key).into());
+        }
+        let can_trade = if is_deposit {
+            pool.can_bid(mint_idx)
+        } else {
+            pool.can_ask(mint_idx)
+        };
+        if !can_trade {
+            return Err(AmmError::MintNotTradable(*mint).into());
+        }
+
+        let mut pool_without_fee = pool.clone();
+        pool_without_fee.fee = 0;
+        let (out_amount, out_amount_without_fee) = if is_deposit {
+            let mut amounts = vec![0; pool.mints.len()];
+            amounts[mint_idx] = *amount;
+            let calc_lpt = |pool: &Pool| {
+                pool.calc_lpt_by_side(amounts.clone(), self.lpt_supply)
+                    .ok_or(AmmError::MathOverflow(ErrorCode::CalcSideSizeLpt))
+            };
+            (calc_lpt(pool)?, calc_lpt(&pool_without_fee)?)
+        } else {
+            if *amount >= self.lpt_supply {
+                return Ok(Quote {
+                    not_enough_liquidity: true,
+                    fee_mint: *output_mint,
+                    ..Quote::default()
+                });
+            }
+            let calc_withdraw = |pool: &Pool| {
+                pool.calc_withdraw_single(*amount, *mint, self.lpt_supply)
+                    .ok_or(AmmError::MathOverflow(ErrorCode::CalcWithdrawSingle))
+            };
+            (calc_withdraw(pool)?, calc_withdraw(&pool_without_fee)?)
+        };
+        let fee_amount = out_amount_without_fee.saturating_sub(out_amount);
+        let fee_pct = if out_amount_without_fee == 0 {
+            Decimal::ZERO
+        } else {
+            fixed_point::div_up(fee_amount.into(), out_amount_without_fee.into())
+                .map(fixed_to_decimal)
+                .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?
+        };
+
+        // An LP token is worth reserve / (weight * supply) of each mint
+        let total_weight: u128 = pool.weights.iter().map(|weight| u128::from(*weight)).sum();
+        let weighted_reserve = u128::from(pool.reserves[mint_idx]) * total_weight;
+        let weighted_supply = u128::from(pool.weights[mint_idx]) * u128::from(self.lpt_supply);
+        let spot_out_amount = if is_deposit {
+            fixed_point::mul_div_down((*amount).into(), weighted_supply, weighted_reserve)
+        } else {
+            fixed_point::mul_div_down((*amount).into(), weighted_reserve, weighted_supply)
+        }
+        .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
+        let price_impact_pct = if spot_out_amount == 0 {
+            Decimal::ZERO
+        } else {
+            fixed_point::div_down(out_amount_without_fee.into(), spot_out_amount)
+                .map(|ratio| fixed_to_decimal(ONE.saturating_sub(ratio)))
+                .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?
+        };
+
+        Ok(Quote {
+            min_out_amount: Some(out_amount),
+            in_amount: *amount,
+            out_amount,
+            fee_amount,
+            fee_mint: *output_mint,
+            fee_pct,
+            price_impact_pct,
+            ..Quote::default()
         })
     }
 
@@ -33,6 +295,12 @@ impl BalansolAmm {
             label: self.label.clone(),
             program_id: self.program_id.clone(),
             pool: self.pool.clone(),
+            lbp_pool: self.lbp_pool.clone(),
+            unix_timestamp: self.unix_timestamp,
+            lp_routing: self.lp_routing,
+            lpt_supply: self.lpt_supply,
+            max_in_ratio: self.max_in_ratio,
+            max_out_ratio: self.max_out_ratio,
         }
     }
 }
@@ -47,64 +315,1201 @@ impl Amm for BalansolAmm {
     }
 
     fn get_reserve_mints(&self) -> Vec<Pubkey> {
-        self.pool.mints.clone()
+        let mut mints = self.pool.mints.clone();
+        if self.lp_routing {
+            mints.push(self.pool.mint_lpt);
+        }
+        mints
+    }
+
+    fn get_reserve_mints_with_direction(&self) -> Vec<ReserveMint> {
+        // The Balansol swap instruction only takes static pools, liquidity
+        // bootstrapping pools can be quoted but not routed
+        if self.lbp_pool.is_some() {
+            return vec![];
+        }
+        let is_active = self.pool.valid_pool_states(vec![PoolState::Initialized]);
+        let mut reserve_mints: Vec<ReserveMint> = self
+            .pool
+            .mints
+            .iter()
+            .enumerate()
+            .map(|(idx, mint)| ReserveMint {
+                mint: *mint,
+                input: is_active && self.pool.can_bid(idx),
+                output: is_active && self.pool.can_ask(idx),
+            })
+            .collect();
+        // Deposits and withdrawals can be quoted, but there is no swap leg
+        // for them so the router must not go through the LP mint
+        if self.lp_routing {
+            reserve_mints.push(ReserveMint {
+                mint: self.pool.mint_lpt,
+                input: false,
+                output: false,
+            });
+        }
+        reserve_mints
     }
 
     fn get_accounts_to_update(&self) -> Vec<Pubkey> {
-        vec![self.key]
+        let mut accounts = vec![self.key];
+        if self.lbp_pool.is_some() {
+            accounts.push(sysvar::clock::ID);
+        }
+        if self.lp_routing {
+            accounts.push(self.pool.mint_lpt);
+        }
+        accounts
     }
 
     fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
-        let pool_account = accounts_map.get(&self.key).unwrap();
-        let pool: Pool = Pool::try_deserialize(&mut pool_account.as_ref()).unwrap();
+        let pool_account = accounts_map
+            .get(&self.key)
+            .ok_or(AmmError::MissingAccount(self.key))?;
+        if self.lbp_pool.is_some() {
+            let clock_account = accounts_map
+                .get(&sysvar::clock::ID)
+                .ok_or(AmmError::MissingAccount(sysvar::clock::ID))?;
+            self.unix_timestamp = bincode::deserialize::<Clock>(clock_account)?.unix_timestamp;
+        }
+        let (pool, lbp_pool) = Self::deserialize_pool(self.key, pool_account, self.unix_timestamp)?;
         self.pool = pool;
+        self.lbp_pool = lbp_pool;
+        if self.lp_routing {
+            let mint_lpt_account = accounts_map
+                .get(&self.pool.mint_lpt)
+                .ok_or(AmmError::MissingAccount(self.pool.mint_lpt))?;
+            self.lpt_supply = spl_token::state::Mint::unpack(mint_lpt_account)?.supply;
+        }
         Ok(())
     }
 
     fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
         let QuoteParams {
-            in_amount,
+            amount,
             input_mint,
             output_mint,
+            swap_mode,
         } = quote_params;
         let pool = &self.pool;
 
+        if self.is_lp_swap(*input_mint, *output_mint) {
+            return self.quote_lp(quote_params);
+        }
+        let (bid_mint_idx, ask_mint_idx) = self.get_swap_mint_indexes(*input_mint, *output_mint)?;
+        if pool.reserves[bid_mint_idx] == 0 || pool.reserves[ask_mint_idx] == 0 {
+            return Err(AmmError::PoolNotTradable(self.key).into());
+        }
+
+        let in_amount = match swap_mode {
+            SwapMode::ExactIn => *amount,
+            SwapMode::ExactOut => {
+                // Gross the requested amount up by the taxman cut, rounding up
+                let tax_complement = PRECISION_U128
+                    .checked_sub(pool.tax.into())
+                    .filter(|tax_complement| *tax_complement > 0)
+                    .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
+                let ask_amount = u128::from(*amount)
+                    .checked_mul(PRECISION_U128)
+                    .and_then(|amount| amount.checked_add(tax_complement - 1))
+                    .and_then(|amount| amount.checked_div(tax_complement))
+                    .and_then(|amount| amount.to_u64())
+                    .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
+                if ask_amount >= pool.reserves[ask_mint_idx] {
+                    return Ok(Quote {
+                        not_enough_liquidity: true,
+                        fee_mint: *output_mint,
+                        ..Quote::default()
+                    });
+                }
+                pool.calc_bid_amount_swap(ask_amount, *input_mint, *output_mint, pool.fee)
+                    .ok_or(AmmError::MathOverflow(ErrorCode::CalcSwap))?
+            }
+        };
+
         let ask_amount = pool
-            .calc_ask_amount_swap(*in_amount, *input_mint, *output_mint, pool.fee)
-            .unwrap();
+            .calc_ask_amount_swap(in_amount, *input_mint, *output_mint, pool.fee)
+            .ok_or(AmmError::MathOverflow(ErrorCode::CalcSwap))?;
 
-        let tax_amount = ask_amount
-            .to_u128()
-            .unwrap()
-            .checked_mul(pool.tax.to_u128().unwrap())
-            .unwrap()
-            .checked_div(PRECISION_U128)
-            .unwrap()
-            .to_u64()
-            .unwrap();
+        let tax_amount = u128::from(ask_amount)
+            .checked_mul(pool.tax.into())
+            .and_then(|amount| amount.checked_div(PRECISION_U128))
+            .and_then(|amount| amount.to_u64())
+            .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
 
         let return_amount = ask_amount
             .checked_sub(tax_amount)
-            .unwrap()
-            .to_u64()
-            .unwrap();
+            .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
+
+        // The LP fee is taken from the bid, express it in the ask mint so it
+        // adds up with the tax
+        let ask_amount_without_fee = pool
+            .calc_ask_amount_swap(in_amount, *input_mint, *output_mint, 0)
+            .ok_or(AmmError::MathOverflow(ErrorCode::CalcSwap))?;
+        let fee_amount = ask_amount_without_fee
+            .checked_sub(return_amount)
+            .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
+        let fee_pct = if ask_amount_without_fee == 0 {
+            Decimal::ZERO
+        } else {
+            fixed_point::div_up(fee_amount.into(), ask_amount_without_fee.into())
+                .map(fixed_to_decimal)
+                .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?
+        };
+
+        // Compare the price paid without fees against the marginal price
+        let spot_price = pool
+            .spot_price(*input_mint, *output_mint, 0)
+            .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?;
+        let price_impact_pct = if in_amount == 0 {
+            Decimal::ZERO
+        } else {
+            match pool.effective_price(in_amount, *input_mint, *output_mint, 0) {
+                Some(effective_price) => fixed_point::div_down(spot_price, effective_price)
+                    .map(|ratio| fixed_to_decimal(ONE.saturating_sub(ratio)))
+                    .ok_or(AmmError::MathOverflow(ErrorCode::Overflow))?,
+                // Nothing comes out of the pool
+                None => Decimal::ONE,
+            }
+        };
+
+        // The pool could pay it, but not within the configured share of its reserves
+        let exceeds_max_ratios = pool
+            .check_swap_ratios(
+                in_amount,
+                *input_mint,
+                ask_amount,
+                *output_mint,
+                self.max_in_ratio,
+                self.max_out_ratio,
+            )
+            .is_err();
 
         Ok(Quote {
-            in_amount: *in_amount,
+            not_enough_liquidity: ask_amount >= pool.reserves[ask_mint_idx] || exceeds_max_ratios,
+            min_out_amount: Some(match swap_mode {
+                SwapMode::ExactIn => return_amount,
+                SwapMode::ExactOut => *amount,
+            }),
+            in_amount,
             out_amount: return_amount,
+            fee_amount,
             fee_mint: *output_mint,
+            fee_pct,
+            price_impact_pct,
             ..Quote::default()
         })
     }
 
-    fn get_swap_leg_and_account_metas(&self, swap_params: &SwapParams) -> Result<bool> {
-        // Reference
-        // 1. balansol/instruction/balansol_swap.rs
-        // 2. balansol/balansol-idl.json
-        Ok(false)
+    fn simulate_and_apply(&mut self, quote_params: &QuoteParams) -> Result<Quote> {
+        let QuoteParams {
+            input_mint,
+            output_mint,
+            ..
+        } = quote_params;
+        if self.is_lp_swap(*input_mint, *output_mint) {
+            return Err(AmmError::NotSupported("Applying LP swaps".to_string()).into());
+        }
+        // An ExactOut quote the pool cannot fill has a zero input and applies nothing
+        let quote = self.quote(quote_params)?;
+        let return_amount = self
+            .pool
+            .apply_swap(*input_mint, *output_mint, quote.in_amount)
+            .ok_or(AmmError::MathOverflow(ErrorCode::CalcSwap))?;
+        debug_assert_eq!(return_amount, quote.out_amount);
+        if let Some(lbp_pool) = &mut self.lbp_pool {
+            lbp_pool.reserves = self.pool.reserves.clone();
+        }
+        Ok(quote)
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            destination_mint,
+            user_source_token_account,
+            user_destination_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+        let pool = &self.pool;
+
+        if self.lbp_pool.is_some() {
+            return Err(AmmError::NotSupported(
+                "Liquidity bootstrapping pool swap legs".to_string(),
+            )
+            .into());
+        }
+        if self.is_lp_swap(*source_mint, *destination_mint) {
+            // Jupiter has no Balansol deposit or withdraw swap
+            return Err(AmmError::NotSupported("LP swap legs".to_string()).into());
+        }
+
+        let (bid_mint_idx, ask_mint_idx) =
+            self.get_swap_mint_indexes(*source_mint, *destination_mint)?;
+
+        // Same layout as balansol/instructions/swap.rs
+        let (treasurer, _) =
+            Pubkey::find_program_address(&[b"treasurer", &self.key.to_bytes()], &self.program_id);
+        let account_metas = jupiter::accounts::BalansolSwap {
+            swap_program: self.program_id,
+            authority: *user_transfer_authority,
+            pool: self.key,
+            tax_man: pool.taxman,
+            bid_mint: *source_mint,
+            treasurer,
+            src_treasury: pool.treasuries[bid_mint_idx],
+            src_associated_token_account: *user_source_token_account,
+            ask_mint: *destination_mint,
+            dst_treasury: pool.treasuries[ask_mint_idx],
+            dst_associated_token_account: *user_destination_token_account,
+            dst_token_account_taxman: associated_token::get_associated_token_address(
+                &pool.taxman,
+                destination_mint,
+            ),
+            system_program: anchor_lang::system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+        }
+        .to_account_metas(None);
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap {
+                swap: Swap::Balansol,
+            },
+            account_metas,
+        })
     }
 
     fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
         Box::new(self.clone())
     }
 }
+
+#[cfg(test)]
+pub(crate) fn mock_keyed_account(pool: &Pool) -> KeyedAccount {
+    use solana_sdk::account::Account;
+
+    let mut data = vec![];
+    pool.try_serialize(&mut data).unwrap();
+    KeyedAccount {
+        key: Pubkey::new_unique(),
+        account: Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        },
+        params: None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn mock_pool(reserves: Vec<u64>, weights: Vec<u64>) -> Pool {
+    let mints: Vec<Pubkey> = reserves.iter().map(|_| Pubkey::new_unique()).collect();
+    Pool {
+        authority: Pubkey::new_unique(),
+        fee: 2_500_000, // 0.25%
+        tax: 500_000,   // 0.05%
+        state: crate::PoolState::Initialized,
+        mint_lpt: Pubkey::new_unique(),
+        taxman: Pubkey::new_unique(),
+        actions: mints
+            .iter()
+            .map(|_| crate::MintActionState::Active)
+            .collect(),
+        treasuries: mints.iter().map(|_| Pubkey::new_unique()).collect(),
+        mints,
+        reserves,
+        weights,
+    }
+}
+
+#[test]
+fn test_quote_usdc_sntr_fixture() {
+    use crate::amms::test_harness::AmmTestHarness;
+    use solana_sdk::pubkey;
+
+    // Devnet
+    pub const USDC_MINT: Pubkey = pubkey!("2z6Ci38Cx6PyL3tFrT95vbEeB3izqpoLdxxBkJk2euyj");
+    pub const SNTR_MINT: Pubkey = pubkey!("5YwUkPdXLoujGkZuo9B4LsLKj3hdkDcfP4derpspifSJ");
+    const USDC_SNTR_POOL: Pubkey = pubkey!("HVBPjtbK4Hrk7DHj1GjaFwprj5oLmQKoGMBti1rZQKuo");
+
+    let test_harness = AmmTestHarness::new();
+
+    let keyed_account = test_harness.get_keyed_account(USDC_SNTR_POOL).unwrap();
+    let mut amm = BalansolAmm::from_keyed_account(&keyed_account).unwrap();
+    test_harness.update_amm(&mut amm);
+
+    let quote = amm
+        .quote(&QuoteParams {
+            input_mint: USDC_MINT,
+            amount: 1000000000, // 1USDC
+            output_mint: SNTR_MINT,
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+
+    // 25_000 USDC / 500_000 SNTR 50-50 pool with a 0.25% fee and 0.05% tax.
+    // The exact rational result is 19_939_229_424, the fixed-point power
+    // error bound rounds it down by 5
+    assert_eq!(quote.in_amount, 1000000000);
+    assert_eq!(quote.out_amount, 19939229419);
+    assert_eq!(quote.fee_amount, 59970607);
+    assert!(!quote.not_enough_liquidity);
+}
+
+#[test]
+fn test_swap_leg_account_metas_match_idl() {
+    use serde_json::Value;
+
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let keyed_account = mock_keyed_account(&pool);
+    let amm = BalansolAmm::from_keyed_account(&keyed_account).unwrap();
+
+    let user = Pubkey::new_unique();
+    let user_source_token_account = Pubkey::new_unique();
+    let user_destination_token_account = Pubkey::new_unique();
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: pool.mints[0],
+            destination_mint: pool.mints[1],
+            user_source_token_account,
+            user_destination_token_account,
+            user_transfer_authority: user,
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::Balansol
+        }
+    ));
+
+    let idl: Value = serde_json::from_str(include_str!("../../../jupiter/idl.json")).unwrap();
+    let idl_accounts = idl["instructions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|ix| ix["name"] == "balansolSwap")
+        .unwrap()["accounts"]
+        .as_array()
+        .unwrap()
+        .clone();
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), idl_accounts.len());
+
+    let (treasurer, _) = Pubkey::find_program_address(
+        &[b"treasurer", &keyed_account.key.to_bytes()],
+        &keyed_account.account.owner,
+    );
+    let expected_keys = [
+        ("swapProgram", keyed_account.account.owner),
+        ("authority", user),
+        ("pool", keyed_account.key),
+        ("taxMan", pool.taxman),
+        ("bidMint", pool.mints[0]),
+        ("treasurer", treasurer),
+        ("srcTreasury", pool.treasuries[0]),
+        ("srcAssociatedTokenAccount", user_source_token_account),
+        ("askMint", pool.mints[1]),
+        ("dstTreasury", pool.treasuries[1]),
+        ("dstAssociatedTokenAccount", user_destination_token_account),
+        (
+            "dstTokenAccountTaxman",
+            associated_token::get_associated_token_address(&pool.taxman, &pool.mints[1]),
+        ),
+        ("systemProgram", anchor_lang::system_program::ID),
+        ("tokenProgram", token::ID),
+        ("associatedTokenProgram", associated_token::ID),
+        ("rent", sysvar::rent::ID),
+    ];
+    for ((meta, idl_account), (name, key)) in account_metas
+        .iter()
+        .zip(idl_accounts.iter())
+        .zip(expected_keys.iter())
+    {
+        assert_eq!(idl_account["name"], *name);
+        assert_eq!(meta.pubkey, *key, "{}", name);
+        assert_eq!(
+            Value::Bool(meta.is_writable),
+            idl_account["isMut"],
+            "{}",
+            name
+        );
+        assert_eq!(
+            Value::Bool(meta.is_signer),
+            idl_account["isSigner"],
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_swap_leg_unknown_mint() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+
+    let result = amm.get_swap_leg_and_account_metas(&SwapParams {
+        source_mint: Pubkey::new_unique(),
+        destination_mint: pool.mints[1],
+        user_source_token_account: Pubkey::new_unique(),
+        user_destination_token_account: Pubkey::new_unique(),
+        user_transfer_authority: Pubkey::new_unique(),
+        open_order_address: None,
+        quote_mint_to_referrer: None,
+        in_amount: 1_000_000,
+    });
+    assert!(matches!(
+        expect_amm_error(result.map(|_| ())),
+        AmmError::UnknownMint(_)
+    ));
+}
+
+#[test]
+fn test_quote_exact_out_covers_requested_amount() {
+    let pool = mock_pool(vec![2_000_000_000_000, 150_000_000_000_000], vec![2, 8]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+
+    for amount in [1, 1_000, 1_000_000_000, 75_000_000_000_000] {
+        let exact_out = amm
+            .quote(&QuoteParams {
+                amount,
+                input_mint: pool.mints[0],
+                output_mint: pool.mints[1],
+                swap_mode: SwapMode::ExactOut,
+            })
+            .unwrap();
+        assert!(exact_out.out_amount >= amount);
+
+        let exact_in = amm
+            .quote(&QuoteParams {
+                amount: exact_out.in_amount,
+                input_mint: pool.mints[0],
+                output_mint: pool.mints[1],
+                swap_mode: SwapMode::ExactIn,
+            })
+            .unwrap();
+        assert_eq!(exact_in.in_amount, exact_out.in_amount);
+        assert_eq!(exact_in.out_amount, exact_out.out_amount);
+    }
+}
+
+#[cfg(test)]
+fn expect_amm_error(result: Result<impl std::fmt::Debug>) -> AmmError {
+    match result.unwrap_err().downcast::<AmmError>() {
+        std::result::Result::Ok(err) => err,
+        Err(err) => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn test_from_keyed_account_bad_discriminator() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let mut keyed_account = mock_keyed_account(&pool);
+    keyed_account.account.data[0] ^= 0xff;
+    assert!(matches!(
+        expect_amm_error(BalansolAmm::from_keyed_account(&keyed_account).map(|amm| amm.key)),
+        AmmError::BadDiscriminator(key) if key == keyed_account.key
+    ));
+
+    keyed_account.account.data.truncate(4);
+    assert!(matches!(
+        expect_amm_error(BalansolAmm::from_keyed_account(&keyed_account).map(|amm| amm.key)),
+        AmmError::BadDiscriminator(_)
+    ));
+}
+
+#[test]
+fn test_from_keyed_account_truncated_data() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let mut keyed_account = mock_keyed_account(&pool);
+    keyed_account
+        .account
+        .data
+        .truncate(ACCOUNT_DISCRIMINATOR + 16);
+    assert!(BalansolAmm::from_keyed_account(&keyed_account).is_err());
+}
+
+#[test]
+fn test_from_keyed_account_malformed_pool() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let mut short_reserves = pool.clone();
+    short_reserves.reserves.pop();
+    let mut short_treasuries = pool.clone();
+    short_treasuries.treasuries.pop();
+    let mut long_weights = pool.clone();
+    long_weights.weights.push(1);
+    let mut short_actions = pool.clone();
+    short_actions.actions.clear();
+    for malformed in [
+        short_reserves,
+        short_treasuries,
+        long_weights,
+        short_actions,
+    ] {
+        let keyed_account = mock_keyed_account(&malformed);
+        assert!(matches!(
+            expect_amm_error(BalansolAmm::from_keyed_account(&keyed_account).map(|amm| amm.key)),
+            AmmError::MalformedPool(key) if key == keyed_account.key
+        ));
+    }
+
+    let mut lbp_pool = mock_lbp_pool(&pool, vec![1, 1], 0, 100);
+    lbp_pool.start_weights.pop();
+    lbp_pool.end_weights.pop();
+    let mut keyed_account = mock_keyed_account(&pool);
+    keyed_account.account.data.clear();
+    lbp_pool
+        .try_serialize(&mut keyed_account.account.data)
+        .unwrap();
+    assert!(matches!(
+        expect_amm_error(BalansolAmm::from_keyed_account(&keyed_account).map(|amm| amm.key)),
+        AmmError::MalformedPool(_)
+    ));
+
+    // Updates are checked as well, the last good pool is kept
+    let mut amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let mut malformed = pool.clone();
+    malformed.treasuries.pop();
+    let mut data = vec![];
+    malformed.try_serialize(&mut data).unwrap();
+    let accounts_map = HashMap::from([(amm.key, data)]);
+    assert!(matches!(
+        expect_amm_error(amm.update(&accounts_map)),
+        AmmError::MalformedPool(_)
+    ));
+    assert_eq!(amm.pool.treasuries.len(), 2);
+}
+
+#[test]
+fn test_update_missing_account() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let mut amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    assert!(matches!(
+        expect_amm_error(amm.update(&HashMap::new())),
+        AmmError::MissingAccount(key) if key == amm.key
+    ));
+}
+
+#[test]
+fn test_quote_errors() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let quote = |amount, input_mint, output_mint, swap_mode| {
+        amm.quote(&QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode,
+        })
+    };
+
+    let unknown_mint = Pubkey::new_unique();
+    assert!(matches!(
+        expect_amm_error(quote(1, unknown_mint, pool.mints[1], SwapMode::ExactIn)),
+        AmmError::UnknownMint(mint) if mint == unknown_mint
+    ));
+    assert!(matches!(
+        expect_amm_error(quote(1, pool.mints[0], pool.mints[0], SwapMode::ExactIn)),
+        AmmError::SameMint(_)
+    ));
+
+    let pool = mock_pool(vec![0, 5_000_000_000], vec![1, 1]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    assert!(matches!(
+        expect_amm_error(amm.quote(&QuoteParams {
+            amount: 1,
+            input_mint: pool.mints[0],
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactIn,
+        })),
+        AmmError::PoolNotTradable(_)
+    ));
+}
+
+#[test]
+fn test_quote_math_overflow() {
+    let mut pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    // A fee above 100% cannot be applied
+    pool.fee = PRECISION_U64 + 1;
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    assert!(matches!(
+        expect_amm_error(amm.quote(&QuoteParams {
+            amount: 1_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactIn,
+        })),
+        AmmError::MathOverflow(ErrorCode::CalcSwap)
+    ));
+
+    let mut pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    // The whole output goes to the taxman
+    pool.tax = PRECISION_U64;
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    assert!(matches!(
+        expect_amm_error(amm.quote(&QuoteParams {
+            amount: 1_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactOut,
+        })),
+        AmmError::MathOverflow(ErrorCode::Overflow)
+    ));
+}
+
+#[test]
+fn test_quote_fees_and_price_impact() {
+    let pool = mock_pool(vec![1_000_000_000_000, 1_000_000_000_000], vec![1, 1]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let quote = amm
+        .quote(&QuoteParams {
+            amount: 10_000_000_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+
+    // Without fees: 10^12 - 10^24 / (10^12 + 10^10) = 9_900_990_099.0099,
+    // the power error bound rounds it one unit further down
+    let ask_amount_without_fee = 9_900_990_098;
+    assert_eq!(quote.in_amount, 10_000_000_000);
+    assert_eq!(quote.fee_mint, pool.mints[1]);
+    assert_eq!(quote.fee_amount + quote.out_amount, ask_amount_without_fee);
+    assert_eq!(quote.min_out_amount, Some(quote.out_amount));
+    assert!(!quote.not_enough_liquidity);
+    // 0.25% LP fee on the bid side (slightly less once converted through
+    // the curve) and 0.05% tax on the ask side
+    let fee_pct = quote.fee_pct.to_f64().unwrap();
+    assert!((fee_pct - 0.002_974_07).abs() < 1e-7, "{}", fee_pct);
+    // 1% of the bid reserve moves the price by about 1%
+    let price_impact_pct = quote.price_impact_pct.to_f64().unwrap();
+    assert!(
+        (price_impact_pct - 0.009_900_99).abs() < 1e-6,
+        "{}",
+        price_impact_pct
+    );
+
+    let dust_quote = amm
+        .quote(&QuoteParams {
+            amount: 1_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+    assert!(dust_quote.price_impact_pct < quote.price_impact_pct);
+}
+
+#[test]
+fn test_quote_not_enough_liquidity() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let quote = amm
+        .quote(&QuoteParams {
+            amount: 5_000_000_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactOut,
+        })
+        .unwrap();
+    assert!(quote.not_enough_liquidity);
+    assert_eq!(quote.out_amount, 0);
+}
+
+#[test]
+fn test_quote_max_ratios() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let quote_params = |amount, swap_mode| QuoteParams {
+        amount,
+        input_mint: pool.mints[0],
+        output_mint: pool.mints[1],
+        swap_mode,
+    };
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let quote = amm
+        .quote(&quote_params(300_000_000, SwapMode::ExactIn))
+        .unwrap();
+    assert!(!quote.not_enough_liquidity);
+    let quote = amm
+        .quote(&quote_params(300_000_001, SwapMode::ExactIn))
+        .unwrap();
+    assert!(quote.not_enough_liquidity);
+    assert!(quote.out_amount > 0);
+    let quote = amm
+        .quote(&quote_params(1_500_000_000, SwapMode::ExactOut))
+        .unwrap();
+    assert!(quote.not_enough_liquidity);
+
+    let mut keyed_account = mock_keyed_account(&pool);
+    keyed_account.params = Some(serde_json::json!({
+        "maxInRatio": 500_000_000,
+        "maxOutRatio": 500_000_000,
+    }));
+    let amm = BalansolAmm::from_keyed_account(&keyed_account).unwrap();
+    let quote = amm
+        .quote(&quote_params(300_000_001, SwapMode::ExactIn))
+        .unwrap();
+    assert!(!quote.not_enough_liquidity);
+    let quote = amm
+        .clone_amm()
+        .quote(&quote_params(1_500_000_000, SwapMode::ExactOut));
+    assert!(!quote.unwrap().not_enough_liquidity);
+}
+
+#[test]
+fn test_quote_rejects_untradable_states() {
+    use crate::MintActionState;
+
+    let quote = |pool: &Pool, input_mint: Pubkey, output_mint: Pubkey| {
+        let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(pool)).unwrap();
+        amm.quote(&QuoteParams {
+            amount: 1_000,
+            input_mint,
+            output_mint,
+            swap_mode: SwapMode::ExactIn,
+        })
+    };
+
+    for state in [
+        PoolState::Uninitialized,
+        PoolState::Frozen,
+        PoolState::Deleted,
+        PoolState::Initializing,
+    ] {
+        let mut pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+        pool.state = state;
+        assert!(matches!(
+            expect_amm_error(quote(&pool, pool.mints[0], pool.mints[1])),
+            AmmError::PoolNotTradable(_)
+        ));
+    }
+
+    let mut pool = mock_pool(
+        vec![1_000_000_000, 5_000_000_000, 5_000_000_000],
+        vec![1, 1, 1],
+    );
+    pool.actions = vec![
+        MintActionState::BidOnly,
+        MintActionState::AskOnly,
+        MintActionState::Paused,
+    ];
+    assert!(quote(&pool, pool.mints[0], pool.mints[1]).is_ok());
+    for (input_mint, output_mint, rejected_mint) in [
+        (pool.mints[1], pool.mints[0], pool.mints[1]),
+        (pool.mints[2], pool.mints[1], pool.mints[2]),
+        (pool.mints[0], pool.mints[2], pool.mints[2]),
+    ] {
+        assert!(matches!(
+            expect_amm_error(quote(&pool, input_mint, output_mint)),
+            AmmError::MintNotTradable(mint) if mint == rejected_mint
+        ));
+    }
+}
+
+#[test]
+fn test_reserve_mints_with_direction() {
+    use crate::MintActionState;
+
+    let mut pool = mock_pool(vec![1, 1, 1, 1], vec![1, 1, 1, 1]);
+    pool.actions = vec![
+        MintActionState::Active,
+        MintActionState::BidOnly,
+        MintActionState::AskOnly,
+        MintActionState::Paused,
+    ];
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let directions: Vec<(bool, bool)> = amm
+        .get_reserve_mints_with_direction()
+        .iter()
+        .map(|reserve_mint| (reserve_mint.input, reserve_mint.output))
+        .collect();
+    assert_eq!(
+        directions,
+        vec![(true, true), (true, false), (false, true), (false, false)]
+    );
+
+    pool.state = PoolState::Frozen;
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    assert!(amm
+        .get_reserve_mints_with_direction()
+        .iter()
+        .all(|reserve_mint| !reserve_mint.input && !reserve_mint.output));
+}
+
+#[cfg(test)]
+pub(crate) fn mock_lp_amm(pool: &Pool, lpt_supply: u64) -> BalansolAmm {
+    let mut keyed_account = mock_keyed_account(pool);
+    keyed_account.params = Some(serde_json::json!({ "lpRouting": true }));
+    let mut amm = BalansolAmm::from_keyed_account(&keyed_account).unwrap();
+
+    let mut mint_lpt_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        supply: lpt_supply,
+        decimals: MINT_LPT_DECIMALS,
+        is_initialized: true,
+        ..spl_token::state::Mint::default()
+    }
+    .pack_into_slice(&mut mint_lpt_data);
+    let accounts_map = HashMap::from([
+        (keyed_account.key, keyed_account.account.data),
+        (pool.mint_lpt, mint_lpt_data),
+    ]);
+    amm.update(&accounts_map).unwrap();
+    amm
+}
+
+#[test]
+fn test_lp_routing_is_opt_in() {
+    let pool = mock_pool(vec![1_000_000_000, 2_000_000_000], vec![1, 1]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    assert_eq!(amm.get_reserve_mints(), pool.mints);
+    assert_eq!(amm.get_accounts_to_update(), vec![amm.key()]);
+    let err = expect_amm_error(amm.quote(&QuoteParams {
+        amount: 1_000,
+        input_mint: pool.mints[0],
+        output_mint: pool.mint_lpt,
+        swap_mode: SwapMode::ExactIn,
+    }));
+    assert!(matches!(err, AmmError::UnknownMint(mint) if mint == pool.mint_lpt));
+
+    let amm = mock_lp_amm(&pool, 1_000_000_000);
+    assert_eq!(
+        amm.get_reserve_mints(),
+        vec![pool.mints[0], pool.mints[1], pool.mint_lpt]
+    );
+    assert_eq!(amm.get_accounts_to_update(), vec![amm.key(), pool.mint_lpt]);
+    let reserve_mints = amm.get_reserve_mints_with_direction();
+    assert_eq!(reserve_mints[2].mint, pool.mint_lpt);
+    assert!(!reserve_mints[2].input && !reserve_mints[2].output);
+
+    // The LP mint account is required once LP routing is on
+    let mut amm = mock_lp_amm(&pool, 1_000_000_000);
+    let mut data = vec![];
+    pool.try_serialize(&mut data).unwrap();
+    let err = expect_amm_error(amm.update(&HashMap::from([(amm.key(), data)])));
+    assert!(matches!(err, AmmError::MissingAccount(key) if key == pool.mint_lpt));
+}
+
+#[test]
+fn test_quote_lp_deposit_and_withdraw() {
+    let pool = mock_pool(vec![1_000_000_000, 2_000_000_000], vec![1, 3]);
+    let lpt_supply = 5_000_000_000;
+    let amm = mock_lp_amm(&pool, lpt_supply);
+
+    let deposit = amm
+        .quote(&QuoteParams {
+            amount: 100_000_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mint_lpt,
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+    assert_eq!(
+        Some(deposit.out_amount),
+        pool.calc_lpt_by_side(vec![100_000_000, 0], lpt_supply)
+    );
+    assert_eq!(deposit.fee_mint, pool.mint_lpt);
+    assert!(deposit.fee_amount > 0 && deposit.fee_pct > Decimal::ZERO);
+    assert!(deposit.price_impact_pct > Decimal::ZERO);
+    assert!(deposit.price_impact_pct < Decimal::new(1, 1));
+
+    let withdraw = amm
+        .quote(&QuoteParams {
+            amount: deposit.out_amount,
+            input_mint: pool.mint_lpt,
+            output_mint: pool.mints[0],
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+    assert_eq!(
+        Some(withdraw.out_amount),
+        pool.calc_withdraw_single(deposit.out_amount, pool.mints[0], lpt_supply)
+    );
+    assert!(withdraw.out_amount < 100_000_000);
+    assert_eq!(withdraw.fee_mint, pool.mints[0]);
+
+    // Burning the whole supply cannot be paid out of a single mint
+    let quote = amm
+        .quote(&QuoteParams {
+            amount: lpt_supply,
+            input_mint: pool.mint_lpt,
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+    assert!(quote.not_enough_liquidity);
+
+    let err = expect_amm_error(amm.quote(&QuoteParams {
+        amount: 1_000,
+        input_mint: pool.mints[0],
+        output_mint: pool.mint_lpt,
+        swap_mode: SwapMode::ExactOut,
+    }));
+    assert!(matches!(err, AmmError::NotSupported(_)));
+
+    let err = expect_amm_error(
+        amm.get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: pool.mints[0],
+            destination_mint: pool.mint_lpt,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000,
+        })
+        .map(|_| ()),
+    );
+    assert!(matches!(err, AmmError::NotSupported(_)));
+
+    // Regular swaps are unchanged
+    assert!(amm
+        .quote(&QuoteParams {
+            amount: 1_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mints[1],
+            swap_mode: SwapMode::ExactIn,
+        })
+        .is_ok());
+}
+
+#[test]
+fn test_pool_prices() {
+    // 1_000 USDC (6 decimals) against 10 SOL (9 decimals)
+    let pool = mock_pool(vec![1_000_000_000, 10_000_000_000], vec![1, 1]);
+    let (usdc, sol) = (pool.mints[0], pool.mints[1]);
+
+    assert_eq!(pool.spot_price(usdc, sol, 0), Some(ONE / 10));
+    assert_eq!(
+        pool.ui_spot_price(usdc, 6, sol, 9, 0),
+        Some(Decimal::new(100, 0))
+    );
+    let spot_price_with_fee = pool.spot_price(usdc, sol, pool.fee).unwrap();
+    assert!(spot_price_with_fee > ONE / 10);
+
+    // Buying 1 SOL worth at spot moves the price by about 10%
+    let ui_effective_price = pool
+        .ui_effective_price(100_000_000, usdc, 6, sol, 9, 0)
+        .unwrap();
+    assert!(ui_effective_price > Decimal::new(109, 0));
+    assert!(ui_effective_price < Decimal::new(111, 0));
+    assert_eq!(pool.spot_price(usdc, Pubkey::new_unique(), 0), None);
+}
+
+#[test]
+fn test_simulate_and_apply() {
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let quote_params = |amount, swap_mode| QuoteParams {
+        amount,
+        input_mint: pool.mints[0],
+        output_mint: pool.mints[1],
+        swap_mode,
+    };
+    let mut amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let quote = amm
+        .quote(&quote_params(100_000_000, SwapMode::ExactIn))
+        .unwrap();
+    assert_eq!(
+        amm.simulate_and_apply(&quote_params(100_000_000, SwapMode::ExactIn))
+            .unwrap()
+            .out_amount,
+        quote.out_amount
+    );
+    // The taxman cut leaves the ask reserve along with the user's amount
+    let ask_amount = pool
+        .calc_ask_amount_swap(100_000_000, pool.mints[0], pool.mints[1], pool.fee)
+        .unwrap();
+    assert!(ask_amount > quote.out_amount);
+    assert_eq!(
+        amm.pool.reserves,
+        vec![1_100_000_000, 5_000_000_000 - ask_amount]
+    );
+    let mut pool_after = pool.clone();
+    assert_eq!(
+        pool_after.apply_swap(pool.mints[0], pool.mints[1], 100_000_000),
+        Some(quote.out_amount)
+    );
+    assert_eq!(pool_after.reserves, amm.pool.reserves);
+
+    // The next route sees the reduced liquidity
+    let next_quote = amm
+        .quote(&quote_params(100_000_000, SwapMode::ExactIn))
+        .unwrap();
+    assert!(next_quote.out_amount < quote.out_amount);
+
+    let reserves = amm.pool.reserves.clone();
+    let quote = amm
+        .simulate_and_apply(&quote_params(10_000_000, SwapMode::ExactOut))
+        .unwrap();
+    assert!(quote.out_amount >= 10_000_000);
+    let reserves = vec![reserves[0] + quote.in_amount, amm.pool.reserves[1]];
+    assert_eq!(amm.pool.reserves[0], reserves[0]);
+    let quote = amm
+        .simulate_and_apply(&quote_params(u64::MAX / 2, SwapMode::ExactOut))
+        .unwrap();
+    assert!(quote.not_enough_liquidity);
+    assert_eq!(amm.pool.reserves, reserves);
+
+    assert_eq!(pool_after.apply_swap(pool.mints[0], pool.mints[0], 1), None);
+    let err = expect_amm_error(mock_lp_amm(&pool, 1_000_000_000).simulate_and_apply(
+        &QuoteParams {
+            amount: 1_000,
+            input_mint: pool.mints[0],
+            output_mint: pool.mint_lpt,
+            swap_mode: SwapMode::ExactIn,
+        },
+    ));
+    assert!(matches!(err, AmmError::NotSupported(_)));
+}
+
+#[cfg(test)]
+fn mock_lbp_pool(pool: &Pool, end_weights: Vec<u64>, start_time: i64, end_time: i64) -> LbpPool {
+    LbpPool {
+        authority: pool.authority,
+        fee: pool.fee,
+        tax: pool.tax,
+        state: pool.state,
+        mint_lpt: pool.mint_lpt,
+        taxman: pool.taxman,
+        mints: pool.mints.clone(),
+        actions: pool.actions.clone(),
+        treasuries: pool.treasuries.clone(),
+        reserves: pool.reserves.clone(),
+        start_weights: pool.weights.clone(),
+        end_weights,
+        start_time,
+        end_time,
+    }
+}
+
+#[cfg(test)]
+fn mock_clock_accounts(key: Pubkey, data: &[u8], unix_timestamp: i64) -> HashMap<Pubkey, Vec<u8>> {
+    let clock = Clock {
+        unix_timestamp,
+        ..Clock::default()
+    };
+    HashMap::from([
+        (key, data.to_vec()),
+        (sysvar::clock::ID, bincode::serialize(&clock).unwrap()),
+    ])
+}
+
+#[test]
+fn test_lbp_pool_weights_follow_the_clock() {
+    use crate::Schedule;
+
+    // The project token starts at 90% and ends at 10% of the pool
+    let pool = mock_pool(vec![100_000_000_000, 1_000_000_000], vec![90, 10]);
+    let lbp_pool = mock_lbp_pool(&pool, vec![10, 90], 1_000, 2_000);
+    assert_eq!(lbp_pool.get_weights(1_500), Some(vec![50, 50]));
+    assert_eq!(
+        lbp_pool.get_normalized_weight(pool.mints[0], 1_750),
+        Some(0.3)
+    );
+    assert_eq!(
+        lbp_pool.get_normalized_weight(Pubkey::new_unique(), 1_750),
+        None
+    );
+
+    let mut keyed_account = mock_keyed_account(&pool);
+    keyed_account.account.data = vec![];
+    lbp_pool
+        .try_serialize(&mut keyed_account.account.data)
+        .unwrap();
+    let data = keyed_account.account.data.clone();
+    let mut amm = BalansolAmm::from_keyed_account(&keyed_account).unwrap();
+    assert_eq!(
+        amm.get_accounts_to_update(),
+        vec![amm.key(), sysvar::clock::ID]
+    );
+    let err = expect_amm_error(amm.update(&HashMap::from([(amm.key(), data.clone())])));
+    assert!(matches!(err, AmmError::MissingAccount(key) if key == sysvar::clock::ID));
+
+    // Buying the project token gets cheaper as its weight drops
+    let quote_params = QuoteParams {
+        amount: 1_000_000,
+        input_mint: pool.mints[1],
+        output_mint: pool.mints[0],
+        swap_mode: SwapMode::ExactIn,
+    };
+    let mut out_amounts = vec![];
+    for unix_timestamp in [0, 1_000, 1_500, 2_000, 3_000] {
+        amm.update(&mock_clock_accounts(amm.key(), &data, unix_timestamp))
+            .unwrap();
+        let quote = amm.quote(&quote_params).unwrap();
+        assert_eq!(
+            quote.out_amount,
+            amm.quote_at(&quote_params, unix_timestamp)
+                .unwrap()
+                .out_amount
+        );
+        out_amounts.push(quote.out_amount);
+    }
+    assert_eq!(out_amounts[0], out_amounts[1]);
+    assert!(out_amounts[1] < out_amounts[2] && out_amounts[2] < out_amounts[3]);
+    assert_eq!(out_amounts[3], out_amounts[4]);
+
+    // Quote for a later execution time without waiting for the clock
+    let static_amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&Pool {
+        weights: vec![10, 90],
+        ..pool.clone()
+    }))
+    .unwrap();
+    assert_eq!(
+        amm.clone()
+            .quote_at(&quote_params, 2_000)
+            .unwrap()
+            .out_amount,
+        static_amm.quote(&quote_params).unwrap().out_amount
+    );
+    assert_eq!(
+        static_amm.quote_at(&quote_params, 0).unwrap().out_amount,
+        static_amm.quote(&quote_params).unwrap().out_amount
+    );
+}
+
+#[test]
+fn test_lbp_pool_is_not_routable() {
+    use crate::amm::SwapParams;
+    use crate::routes::Router;
+
+    let pool = mock_pool(vec![100_000_000_000, 1_000_000_000], vec![90, 10]);
+    let mut keyed_account = mock_keyed_account(&pool);
+    keyed_account.account.data = vec![];
+    mock_lbp_pool(&pool, vec![10, 90], 1_000, 2_000)
+        .try_serialize(&mut keyed_account.account.data)
+        .unwrap();
+    let amm = BalansolAmm::from_keyed_account(&keyed_account).unwrap();
+
+    let err = expect_amm_error(
+        amm.get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: pool.mints[1],
+            destination_mint: pool.mints[0],
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000,
+        })
+        .map(|_| ()),
+    );
+    assert!(matches!(err, AmmError::NotSupported(_)));
+    assert!(amm.get_reserve_mints_with_direction().is_empty());
+    assert!(Router::new(vec![&amm])
+        .find_best_route(pool.mints[1], pool.mints[0], 1_000_000, 1)
+        .is_err());
+}
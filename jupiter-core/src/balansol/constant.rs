@@ -3,9 +3,13 @@ pub const PRECISION_F64: f64 = 1000000000_f64; // 10^9;
 pub const PRECISION_U64: u64 = 1000000000_u64; // 10^9;
 pub const PRECISION_U128: u128 = 1000000000_u128; // 10^9;
 pub const MINT_LPT_DECIMALS: u8 = 9;
+// Largest swap as a share of the reserves, in PRECISION
+pub const MAX_IN_RATIO: u64 = 300000000_u64; // 30% of the bid reserve
+pub const MAX_OUT_RATIO: u64 = 300000000_u64; // 30% of the ask reserve
 
 pub const PUBKEY_SIZE: usize = 32;
 pub const U64_SIZE: usize = 8;
+pub const I64_SIZE: usize = 8;
 pub const F64_SIZE: usize = 8;
 pub const U8_SIZE: usize = 1;
 pub const VECTOR_SIZE: usize = 4;
@@ -0,0 +1,187 @@
+use crate::amms::{
+    account_provider::AccountProvider,
+    amm::{Amm, KeyedAccount},
+};
+use crate::balansol_amm::BalansolAmm;
+use crate::pool::Pool;
+use anchor_lang::Discriminator;
+use anyhow::Result;
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+/// `getProgramAccounts` filters matching the `Pool` accounts of a Balansol program
+pub fn pool_filters() -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(Pool::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, Pool::discriminator().to_vec())),
+    ]
+}
+
+/// Orders a mint pair so that both swap directions share a key
+pub fn mint_pair(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey) {
+    if mint_a <= mint_b {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    }
+}
+
+/// Every pool of a Balansol program, indexed by the mint pairs it can swap
+#[derive(Default)]
+pub struct BalansolPools {
+    pub amms: HashMap<Pubkey, BalansolAmm>,
+    pub keys_by_mint_pair: HashMap<(Pubkey, Pubkey), Vec<Pubkey>>,
+    // Accounts that passed the filters but could not be read as pools
+    pub errors: Vec<(Pubkey, anyhow::Error)>,
+}
+
+impl BalansolPools {
+    /// Scans the `Pool` accounts of `program_id`, the Balansol program being `crate::ID`.
+    /// A pool that cannot be read is recorded in `errors` and skipped, so that
+    /// it does not hide the others.
+    pub fn discover(provider: &dyn AccountProvider, program_id: &Pubkey) -> Result<Self> {
+        let mut pools = Self::default();
+        for (key, account) in provider.get_program_accounts(program_id, &pool_filters())? {
+            match BalansolAmm::from_keyed_account(&KeyedAccount {
+                key,
+                account,
+                params: None,
+            }) {
+                Ok(amm) => pools.insert(amm),
+                Err(err) => pools.errors.push((key, err)),
+            }
+        }
+        Ok(pools)
+    }
+
+    pub fn insert(&mut self, amm: BalansolAmm) {
+        let key = amm.key();
+        let mints = amm.get_reserve_mints();
+        for (i, mint_a) in mints.iter().enumerate() {
+            for mint_b in &mints[i + 1..] {
+                let keys = self
+                    .keys_by_mint_pair
+                    .entry(mint_pair(*mint_a, *mint_b))
+                    .or_default();
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        self.amms.insert(key, amm);
+    }
+
+    /// Pools swapping `mint_a` for `mint_b`, in either direction
+    pub fn get_amms(&self, mint_a: Pubkey, mint_b: Pubkey) -> Vec<&BalansolAmm> {
+        self.keys_by_mint_pair
+            .get(&mint_pair(mint_a, mint_b))
+            .map(|keys| keys.iter().filter_map(|key| self.amms.get(key)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.amms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amms.is_empty()
+    }
+}
+
+#[test]
+fn test_discover_pools() {
+    use crate::amms::{account_provider::MemoryAccountProvider, error::AmmError};
+    use crate::balansol_amm::{mock_keyed_account, mock_pool};
+    use crate::lbp_pool::LbpPool;
+    use anchor_lang::AccountSerialize;
+
+    let mut provider = MemoryAccountProvider::new();
+    let mut insert = |pool: &Pool, owner: Pubkey, len: usize| {
+        let mut keyed_account = mock_keyed_account(pool);
+        keyed_account.account.owner = owner;
+        keyed_account.account.data.resize(len, 0);
+        provider.insert(keyed_account.key, keyed_account.account);
+        keyed_account.key
+    };
+    let pair_pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let mut triple_pool = mock_pool(vec![1_000, 2_000, 3_000], vec![1, 1, 2]);
+    triple_pool.mints[0] = pair_pool.mints[1];
+    let pair_key = insert(&pair_pool, crate::ID, Pool::LEN);
+    let triple_key = insert(&triple_pool, crate::ID, Pool::LEN);
+    // Another program, and an account that is not a full size pool
+    insert(&pair_pool, Pubkey::new_unique(), Pool::LEN);
+    insert(&pair_pool, crate::ID, Pool::LEN - 1);
+    // A pool account the Amm cannot read
+    let mut corrupt_pool = pair_pool.clone();
+    corrupt_pool.reserves.pop();
+    let corrupt_key = insert(&corrupt_pool, crate::ID, Pool::LEN);
+    // Same size, other account type
+    let lbp_pool = LbpPool {
+        authority: Pubkey::new_unique(),
+        fee: 0,
+        tax: 0,
+        state: crate::PoolState::Initialized,
+        mint_lpt: Pubkey::new_unique(),
+        taxman: Pubkey::new_unique(),
+        mints: pair_pool.mints.clone(),
+        actions: pair_pool.actions.clone(),
+        treasuries: pair_pool.treasuries.clone(),
+        reserves: pair_pool.reserves.clone(),
+        start_weights: vec![1, 1],
+        end_weights: vec![1, 1],
+        start_time: 0,
+        end_time: 1,
+    };
+    let mut data = vec![];
+    lbp_pool.try_serialize(&mut data).unwrap();
+    data.resize(Pool::LEN, 0);
+    let mut lbp_account = mock_keyed_account(&pair_pool).account;
+    lbp_account.owner = crate::ID;
+    lbp_account.data = data;
+    provider.insert(Pubkey::new_unique(), lbp_account);
+
+    let pools = BalansolPools::discover(&provider, &crate::ID).unwrap();
+    assert_eq!(pools.len(), 2);
+    assert_eq!(pools.errors.len(), 1);
+    assert_eq!(pools.errors[0].0, corrupt_key);
+    assert!(matches!(
+        pools.errors[0].1.downcast_ref::<AmmError>(),
+        Some(AmmError::MalformedPool(key)) if *key == corrupt_key
+    ));
+    assert_eq!(pools.keys_by_mint_pair.len(), 1 + 3);
+
+    let amms = pools.get_amms(pair_pool.mints[1], pair_pool.mints[0]);
+    assert_eq!(amms.len(), 1);
+    assert_eq!(amms[0].key(), pair_key);
+    assert_eq!(amms[0].get_reserve_mints(), pair_pool.mints);
+
+    let amms = pools.get_amms(triple_pool.mints[2], triple_pool.mints[1]);
+    assert_eq!(amms.len(), 1);
+    assert_eq!(amms[0].key(), triple_key);
+
+    // Shared mint, no common pool
+    assert!(pools
+        .get_amms(pair_pool.mints[0], triple_pool.mints[2])
+        .is_empty());
+    assert!(BalansolPools::discover(&provider, &Pubkey::new_unique())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_discover_usdc_sntr_fixture() {
+    use crate::amms::test_harness::AmmTestHarness;
+    use solana_sdk::pubkey;
+
+    // Devnet
+    const USDC_MINT: Pubkey = pubkey!("2z6Ci38Cx6PyL3tFrT95vbEeB3izqpoLdxxBkJk2euyj");
+    const SNTR_MINT: Pubkey = pubkey!("5YwUkPdXLoujGkZuo9B4LsLKj3hdkDcfP4derpspifSJ");
+    const USDC_SNTR_POOL: Pubkey = pubkey!("HVBPjtbK4Hrk7DHj1GjaFwprj5oLmQKoGMBti1rZQKuo");
+
+    let test_harness = AmmTestHarness::new();
+    let pools = BalansolPools::discover(test_harness.provider.as_ref(), &crate::ID).unwrap();
+    let amms = pools.get_amms(SNTR_MINT, USDC_MINT);
+    assert_eq!(amms.len(), 1);
+    assert_eq!(amms[0].key(), USDC_SNTR_POOL);
+}
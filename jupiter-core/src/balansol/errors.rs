@@ -48,4 +48,10 @@ pub enum ErrorCode {
   Slippage,
   #[msg("Too many referrer addresses.")]
   TooManyReferrers,
+  #[msg("Bid amount exceeds the max in ratio")]
+  MaxInRatio,
+  #[msg("Ask amount exceeds the max out ratio")]
+  MaxOutRatio,
+  #[msg("Invalid treasurer")]
+  InvalidTreasurer,
 }
@@ -0,0 +1,165 @@
+use crate::pool::Pool;
+use crate::Accessor;
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// Emitted by the swap instruction once the pool reserves are updated
+#[event]
+pub struct SwapEvent {
+    pub authority: Pubkey,
+    pub pool: Pubkey,
+    pub bid_mint: Pubkey,
+    pub ask_mint: Pubkey,
+    pub bid_amount: u64,
+    pub limit: u64,
+    pub ask_amount: u64,
+    pub total_tax_fee_amount: u64,
+}
+
+/// Decodes the base64 payload of a `Program data:` log line into a `SwapEvent`
+pub fn decode_swap_event(data: &str) -> Option<SwapEvent> {
+    let bytes = base64::decode(data).ok()?;
+    if bytes.len() < 8 || bytes[..8] != SwapEvent::discriminator() {
+        return None;
+    }
+    SwapEvent::try_from_slice(&bytes[8..]).ok()
+}
+
+/// Collects the `SwapEvent`s emitted by `program_id` in the logs of a
+/// transaction, in execution order. Events logged by other programs are
+/// skipped, and a failed transaction emits nothing since its swaps reverted.
+pub fn parse_swap_events(program_id: &Pubkey, logs: &[String]) -> Vec<SwapEvent> {
+    let program_id = program_id.to_string();
+    // Programs currently executing, innermost last
+    let mut invocations: Vec<&str> = vec![];
+    let mut events = vec![];
+    for log in logs {
+        if let Some(data) = log.strip_prefix(PROGRAM_DATA_PREFIX) {
+            if invocations.last() == Some(&program_id.as_str()) {
+                events.extend(decode_swap_event(data));
+            }
+        } else if let Some(log) = log.strip_prefix("Program ") {
+            let mut words = log.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(id), Some("invoke")) => invocations.push(id),
+                (Some(_), Some("success")) => {
+                    invocations.pop();
+                }
+                (Some(_), Some("failed:")) => return vec![],
+                _ => {}
+            }
+        }
+    }
+    events
+}
+
+/// Applies the events of `pool_key` to a cached copy of the pool, returning
+/// how many were applied. Fails if an event does not fit the cached reserves,
+/// which means the cache missed a transaction and must be fetched again.
+pub fn apply_swap_events(
+    pool_key: &Pubkey,
+    pool: &mut Pool,
+    events: &[SwapEvent],
+) -> Option<usize> {
+    let mut replayed = pool.clone();
+    let mut applied = 0;
+    for event in events.iter().filter(|event| event.pool == *pool_key) {
+        replayed.apply_swap_event(event)?;
+        applied += 1;
+    }
+    *pool = replayed;
+    Some(applied)
+}
+
+#[cfg(test)]
+fn program_data_log<T: anchor_lang::Event>(event: &T) -> String {
+    format!("{}{}", PROGRAM_DATA_PREFIX, base64::encode(event.data()))
+}
+
+#[cfg(test)]
+fn mock_swap_event(pool_key: Pubkey, pool: &Pool, bid_amount: u64, ask_amount: u64) -> SwapEvent {
+    SwapEvent {
+        authority: Pubkey::new_unique(),
+        pool: pool_key,
+        bid_mint: pool.mints[0],
+        ask_mint: pool.mints[1],
+        bid_amount,
+        limit: 0,
+        ask_amount,
+        total_tax_fee_amount: ask_amount / 1_000,
+    }
+}
+
+#[test]
+fn test_parse_swap_events() {
+    use crate::balansol_amm::mock_pool;
+    use anchor_lang::Event;
+
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let (pool_key, program_id, router_id) = (Pubkey::new_unique(), crate::ID, Pubkey::new_unique());
+    let first = mock_swap_event(pool_key, &pool, 1_000, 4_000);
+    let second = mock_swap_event(pool_key, &pool, 2_000, 7_000);
+    let logs = vec![
+        format!("Program {} invoke [1]", router_id),
+        "Program log: Instruction: Route".to_string(),
+        format!("Program {} invoke [2]", program_id),
+        "Program log: Instruction: Swap".to_string(),
+        program_data_log(&first),
+        format!(
+            "Program {} consumed 21000 of 180000 compute units",
+            program_id
+        ),
+        format!("Program {} success", program_id),
+        // Same event layout, but logged by the router itself
+        program_data_log(&second),
+        format!("Program {} invoke [2]", program_id),
+        "Program data: not base64".to_string(),
+        "Program data: AAAA".to_string(),
+        program_data_log(&second),
+        format!("Program {} success", program_id),
+        format!("Program {} success", router_id),
+    ];
+    let events = parse_swap_events(&program_id, &logs);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].data(), first.data());
+    assert_eq!(events[1].data(), second.data());
+    assert_eq!(parse_swap_events(&router_id, &logs).len(), 1);
+
+    let mut failed_logs = logs[..7].to_vec();
+    failed_logs.push(format!(
+        "Program {} failed: custom program error: 0x1",
+        router_id
+    ));
+    assert!(parse_swap_events(&program_id, &failed_logs).is_empty());
+}
+
+#[test]
+fn test_apply_swap_events() {
+    use crate::balansol_amm::mock_pool;
+
+    let pool = mock_pool(vec![1_000_000_000, 5_000_000_000], vec![1, 1]);
+    let pool_key = Pubkey::new_unique();
+    let events = vec![
+        mock_swap_event(pool_key, &pool, 1_000, 4_000),
+        mock_swap_event(Pubkey::new_unique(), &pool, 1_000, 4_000),
+        mock_swap_event(pool_key, &pool, 2_000, 7_000),
+    ];
+    let mut cached = pool.clone();
+    assert_eq!(apply_swap_events(&pool_key, &mut cached, &events), Some(2));
+    assert_eq!(cached.reserves, vec![1_000_003_000, 5_000_000_000 - 11_011]);
+
+    // A reserve the cache cannot cover leaves it untouched
+    let overdraft = mock_swap_event(pool_key, &pool, 1, 6_000_000_000);
+    let reserves = cached.reserves.clone();
+    assert_eq!(
+        apply_swap_events(
+            &pool_key,
+            &mut cached,
+            &[mock_swap_event(pool_key, &pool, 1_000, 4_000), overdraft]
+        ),
+        None
+    );
+    assert_eq!(cached.reserves, reserves);
+}
@@ -0,0 +1,2 @@
+pub mod swap;
+pub use swap::*;
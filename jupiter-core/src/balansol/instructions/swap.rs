@@ -1,9 +1,10 @@
 use crate::{
-  constant::PRECISION_U64,
+  constant::PRECISION_U128,
   errors::ErrorCode,
+  events::SwapEvent,
   schema::{
-    pool::{MintActionState, Pool, PoolState, PoolValidation},
-    pool_trait::Exchange,
+    pool::Pool,
+    pool_trait::{Accessor, Exchange, Operation},
   },
 };
 use anchor_lang::prelude::*;
@@ -12,18 +13,6 @@ use num_traits::ToPrimitive;
 
 pub const MAX_SHARES: u64 = 3;
 
-#[event]
-pub struct SwapEvent {
-  pub authority: Pubkey,
-  pub pool: Pubkey,
-  pub bid_mint: Pubkey,
-  pub ask_mint: Pubkey,
-  pub bid_amount: u64,
-  pub limit: u64,
-  pub ask_amount: u64,
-  pub total_tax_fee_amount: u64,
-}
-
 #[derive(Accounts)]
 pub struct Swap<'info> {
   #[account(mut)]
@@ -81,31 +70,127 @@ pub struct Swap<'info> {
 }
 
 impl Swap<'_> {
+  #[allow(clippy::result_large_err)]
   pub fn invoke<'a, 'b, 'c, 'info>(
     ctx: Context<'a, 'b, 'c, 'info, Swap<'info>>,
     bid_amount: u64,
     limit: u64,
   ) -> Result<u64> {
+    if bid_amount == 0 {
+      return err!(ErrorCode::ParamsZero);
+    }
     let pool = &mut ctx.accounts.pool;
+    if !pool.is_active() {
+      return err!(ErrorCode::PoolStopped);
+    }
+    let bid_mint = ctx.accounts.bid_mint.key();
+    let ask_mint = ctx.accounts.ask_mint.key();
+    if bid_mint == ask_mint {
+      return err!(ErrorCode::AccountMint);
+    }
+    let bid_mint_idx = pool
+      .get_mint_index(bid_mint)
+      .ok_or(ErrorCode::AccountMint)?;
+    let ask_mint_idx = pool
+      .get_mint_index(ask_mint)
+      .ok_or(ErrorCode::AccountMint)?;
+    if pool.treasuries[bid_mint_idx] != ctx.accounts.src_treasury.key()
+      || pool.treasuries[ask_mint_idx] != ctx.accounts.dst_treasury.key()
+    {
+      return err!(ErrorCode::AccountTreasury);
+    }
+    if !pool.can_bid(bid_mint_idx) || !pool.can_ask(ask_mint_idx) {
+      return err!(ErrorCode::MintState);
+    }
+
+    // Same math as the off-chain quote: the fee stays in the bid reserve and
+    // the tax is paid out of the ask amount
+    let ask_amount = pool
+      .calc_ask_amount_swap(bid_amount, bid_mint, ask_mint, pool.fee)
+      .ok_or(ErrorCode::CalcSwap)?;
+    let tax_amount = ask_amount
+      .to_u128()
+      .ok_or(ErrorCode::Overflow)?
+      .checked_mul(pool.tax.into())
+      .ok_or(ErrorCode::Overflow)?
+      .checked_div(PRECISION_U128)
+      .ok_or(ErrorCode::Overflow)?
+      .to_u64()
+      .ok_or(ErrorCode::Overflow)?;
+    let return_amount = ask_amount
+      .checked_sub(tax_amount)
+      .ok_or(ErrorCode::Overflow)?;
+    if return_amount < limit {
+      return err!(ErrorCode::Slippage);
+    }
+
+    pool
+      .add_reserve(bid_mint_idx, bid_amount)
+      .ok_or(ErrorCode::Overflow)?;
+    pool
+      .sub_reserve(ask_mint_idx, ask_amount)
+      .ok_or(ErrorCode::Overflow)?;
+
+    let pool_key = pool.key();
+    let treasurer_bump = *ctx.bumps.get("treasurer").ok_or(ErrorCode::InvalidTreasurer)?;
     let seeds: &[&[&[u8]]] = &[&[
       "treasurer".as_ref(),
-      &pool.key().to_bytes(),
-      &[ctx.bumps.treasurer],
+      &pool_key.to_bytes(),
+      &[treasurer_bump],
     ]];
 
-    // TODO: Swap
-    
+    // Deposit the bid
+    token::transfer(
+      CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        token::Transfer {
+          from: ctx.accounts.src_associated_token_account.to_account_info(),
+          to: ctx.accounts.src_treasury.to_account_info(),
+          authority: ctx.accounts.authority.to_account_info(),
+        },
+      ),
+      bid_amount,
+    )?;
+    // Pay the ask
+    token::transfer(
+      CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        token::Transfer {
+          from: ctx.accounts.dst_treasury.to_account_info(),
+          to: ctx.accounts.dst_associated_token_account.to_account_info(),
+          authority: ctx.accounts.treasurer.to_account_info(),
+        },
+        seeds,
+      ),
+      return_amount,
+    )?;
+    // Pay the tax
+    if tax_amount > 0 {
+      token::transfer(
+        CpiContext::new_with_signer(
+          ctx.accounts.token_program.to_account_info(),
+          token::Transfer {
+            from: ctx.accounts.dst_treasury.to_account_info(),
+            to: ctx.accounts.dst_token_account_taxman.to_account_info(),
+            authority: ctx.accounts.treasurer.to_account_info(),
+          },
+          seeds,
+        ),
+        tax_amount,
+      )?;
+    }
+
     emit!(SwapEvent {
       authority: ctx.accounts.authority.key(),
-      pool: pool.key(),
-      bid_mint: ctx.accounts.bid_mint.key(),
-      ask_mint: ctx.accounts.ask_mint.key(),
+      pool: pool_key,
+      bid_mint,
+      ask_mint,
       bid_amount,
       limit,
-      ask_amount,
+      ask_amount: return_amount,
       total_tax_fee_amount: tax_amount,
     });
 
-    Ok(ask_amount)
+    Ok(return_amount)
   }
 }
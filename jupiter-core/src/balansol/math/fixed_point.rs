@@ -0,0 +1,233 @@
+use std::convert::TryFrom;
+
+#[allow(clippy::assign_op_pattern, clippy::manual_range_contains)]
+mod uint_types {
+    use uint::construct_uint;
+
+    construct_uint! {
+      pub struct U256(4);
+    }
+}
+pub use uint_types::U256;
+
+///
+/// Fixed-point arithmetic
+///
+/// Every value is an integer scaled by `ONE` (10^18) so results only depend on
+/// integer operations and are identical on every platform.
+///
+pub const ONE: u128 = 1_000_000_000_000_000_000; // 10^18
+const ONE_I128: i128 = ONE as i128;
+// ln(2) * 10^18, rounded down
+const LN_2: i128 = 693_147_180_559_945_309;
+// exp(x) is below 10^-18 under this bound
+const MIN_EXPONENT: i128 = -42 * ONE_I128;
+// exp(x) * 10^18 overflows u128 above this bound
+const MAX_EXPONENT: i128 = 47 * ONE_I128;
+// Relative error bound of pow (10^-14), used to round its result up or down
+pub const MAX_POW_RELATIVE_ERROR: u128 = 10_000;
+
+pub fn mul_div_down(a: u128, b: u128, c: u128) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+    let result = U256::from(a).checked_mul(U256::from(b))? / U256::from(c);
+    u128::try_from(result).ok()
+}
+
+pub fn mul_div_up(a: u128, b: u128, c: u128) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+    let product = U256::from(a).checked_mul(U256::from(b))?;
+    let mut result = product / U256::from(c);
+    if !(product % U256::from(c)).is_zero() {
+        result += U256::one();
+    }
+    u128::try_from(result).ok()
+}
+
+pub fn mul_down(a: u128, b: u128) -> Option<u128> {
+    mul_div_down(a, b, ONE)
+}
+
+pub fn mul_up(a: u128, b: u128) -> Option<u128> {
+    mul_div_up(a, b, ONE)
+}
+
+pub fn div_down(a: u128, b: u128) -> Option<u128> {
+    mul_div_down(a, ONE, b)
+}
+
+pub fn div_up(a: u128, b: u128) -> Option<u128> {
+    mul_div_up(a, ONE, b)
+}
+
+///
+/// Natural logarithm of a positive fixed-point number
+///
+pub fn ln(x: u128) -> Option<i128> {
+    if x == 0 {
+        return None;
+    }
+    // Split x = m * 2^k with m in [1, 2)
+    let (m, k) = if x >= ONE {
+        let k = 127 - (x / ONE).leading_zeros();
+        (x >> k, k as i128)
+    } else {
+        let mut k = x.leading_zeros() - ONE.leading_zeros();
+        if x << k < ONE {
+            k += 1;
+        }
+        (x << k, -(k as i128))
+    };
+    // ln(m) = 2 * atanh(z) = 2 * (z + z^3/3 + z^5/5 + ...) with z = (m - 1) / (m + 1)
+    let z = div_down(m - ONE, m + ONE)?;
+    let z_squared = mul_down(z, z)?;
+    let mut term = z;
+    let mut sum = z;
+    let mut n = 3;
+    loop {
+        term = mul_down(term, z_squared)?;
+        if term == 0 {
+            break;
+        }
+        sum += term / n;
+        n += 2;
+    }
+    let ln_m = i128::try_from(2 * sum).ok()?;
+    k.checked_mul(LN_2)?.checked_add(ln_m)
+}
+
+///
+/// Natural exponential of a signed fixed-point number
+///
+pub fn exp(x: i128) -> Option<u128> {
+    if x < MIN_EXPONENT {
+        return Some(0);
+    }
+    if x > MAX_EXPONENT {
+        return None;
+    }
+    // Split x = k * ln(2) + r with r in [0, ln(2))
+    let k = x.div_euclid(LN_2);
+    let r = x.rem_euclid(LN_2) as u128;
+    // exp(r) = 1 + r + r^2/2! + ...
+    let mut term = ONE;
+    let mut sum = ONE;
+    let mut n = 1;
+    loop {
+        term = mul_div_down(term, r, n * ONE)?;
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        n += 1;
+    }
+    if k >= 0 {
+        sum.checked_mul(1_u128.checked_shl(k as u32)?)
+    } else {
+        Some(sum.checked_shr((-k) as u32).unwrap_or(0))
+    }
+}
+
+///
+/// base^exponent, both fixed-point, without any error compensation
+///
+pub fn pow(base: u128, exponent: u128) -> Option<u128> {
+    if exponent == 0 || base == ONE {
+        return Some(ONE);
+    }
+    if base == 0 {
+        return Some(0);
+    }
+    if exponent == ONE {
+        return Some(base);
+    }
+    let ln_base = ln(base)?;
+    let product = match ln_base.checked_mul(i128::try_from(exponent).ok()?) {
+        Some(product) => product / ONE_I128,
+        // A tiny base raised to a huge exponent vanishes
+        None if ln_base < 0 => return Some(0),
+        None => return None,
+    };
+    exp(product)
+}
+
+///
+/// base^exponent rounded down past the approximation error
+///
+pub fn pow_down(base: u128, exponent: u128) -> Option<u128> {
+    let raw = pow(base, exponent)?;
+    let max_error = mul_up(raw, MAX_POW_RELATIVE_ERROR)?.checked_add(1)?;
+    Some(raw.saturating_sub(max_error))
+}
+
+///
+/// base^exponent rounded up past the approximation error
+///
+pub fn pow_up(base: u128, exponent: u128) -> Option<u128> {
+    let raw = pow(base, exponent)?;
+    let max_error = mul_up(raw, MAX_POW_RELATIVE_ERROR)?.checked_add(1)?;
+    raw.checked_add(max_error)
+}
+
+#[test]
+fn test_ln_exp_known_values() {
+    assert_eq!(ln(ONE), Some(0));
+    assert_eq!(ln(0), None);
+    // ln(2) = 0.693147180559945309...
+    assert!((ln(2 * ONE).unwrap() - LN_2).abs() <= 2);
+    // ln(0.5) = -ln(2)
+    assert!((ln(ONE / 2).unwrap() + LN_2).abs() <= 2);
+    // ln(10) = 2.302585092994045684...
+    assert!((ln(10 * ONE).unwrap() - 2_302_585_092_994_045_684).abs() <= 50);
+
+    assert_eq!(exp(0), Some(ONE));
+    // e = 2.718281828459045235...
+    assert!((exp(ONE_I128).unwrap() as i128 - 2_718_281_828_459_045_235).abs() <= 50);
+    // e^-1 = 0.367879441171442321...
+    assert!((exp(-ONE_I128).unwrap() as i128 - 367_879_441_171_442_321).abs() <= 50);
+    assert_eq!(exp(-43 * ONE_I128), Some(0));
+    assert_eq!(exp(48 * ONE_I128), None);
+}
+
+#[test]
+fn test_pow_matches_f64() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..10_000 {
+        let base = rng.gen_range(1..100 * ONE);
+        let exponent = rng.gen_range(0..10 * ONE);
+        let expected = (base as f64 / ONE as f64).powf(exponent as f64 / ONE as f64);
+        if !(1e-9..1e15).contains(&expected) {
+            continue;
+        }
+        let down = pow_down(base, exponent).unwrap() as f64 / ONE as f64;
+        let up = pow_up(base, exponent).unwrap() as f64 / ONE as f64;
+        assert!(down <= up);
+        // Relative error, plus the last digits lost on tiny results
+        assert!(
+            (down - expected).abs() < expected * 1e-9 + 1e-16,
+            "{}^{}: {} vs {}",
+            base,
+            exponent,
+            down,
+            expected
+        );
+    }
+}
+
+#[test]
+fn test_pow_monotone() {
+    let exponent = 3 * ONE / 7;
+    let mut last = 0;
+    let mut base = ONE / 1_000;
+    while base < 10 * ONE {
+        let result = pow(base, exponent).unwrap();
+        assert!(result >= last, "pow is not monotone at {}", base);
+        last = result;
+        base += ONE / 997;
+    }
+}
@@ -1,5 +1,10 @@
 pub mod f64_trait;
 pub use f64_trait::*;
 
+pub mod fixed_point;
+
 pub mod oracle;
 pub use oracle::*;
+
+#[cfg(feature = "f64-math")]
+pub mod oracle_f64;
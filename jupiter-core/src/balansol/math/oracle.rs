@@ -1,32 +1,917 @@
 use crate::constant::*;
+use crate::errors::ErrorCode;
 use crate::f64_trait::F64Trait;
+use crate::fixed_point::{self, ONE};
 use num_traits::ToPrimitive;
+use rust_decimal::Decimal;
 
 pub fn normalize_weight(weight_idx: usize, weights: Vec<u64>) -> Option<f64> {
     let total_weight: u64 = weights.iter().sum();
     let weight = weights[weight_idx].to_f64()?;
     return Some(weight.checked_div(total_weight.to_f64()?)?);
-  }
+}
 
+///
+/// Weight of a liquidity bootstrapping pool at `timestamp`, moving linearly
+/// from `start_weight` to `end_weight` over the sale window
+///
+pub fn calc_lbp_weight(
+    start_weight: u64,
+    end_weight: u64,
+    start_time: i64,
+    end_time: i64,
+    timestamp: i64,
+) -> Option<u64> {
+    if timestamp <= start_time {
+        return Some(start_weight);
+    }
+    if timestamp >= end_time {
+        return Some(end_weight);
+    }
+    let elapsed = i128::from(timestamp).checked_sub(start_time.into())?;
+    let duration = i128::from(end_time).checked_sub(start_time.into())?;
+    let weight_delta = i128::from(end_weight).checked_sub(start_weight.into())?;
+    let weight = i128::from(start_weight)
+        .checked_add(weight_delta.checked_mul(elapsed)?.checked_div(duration)?)?;
+    weight.to_u64()
+}
+
+///
+/// Ask amount of a swap, rounded down. It can be a few units below what the
+/// deployed program pays with f64 math but never above it, see the `f64-math`
+/// tests in `oracle_f64`
+///
 pub fn calc_ask_amount_swap(
     bid_amount: u64,
     bid_reserve: u64,
-    bid_weight: f64,
+    bid_weight: u64,
     ask_reserve: u64,
-    ask_weight: f64,
+    ask_weight: u64,
     fee: u64,
-  ) -> Option<u64> {
-    let _fee = fee.to_f64()?.checked_div(PRECISION_F64)?;
-    let _bid_amount = 1_f64.checked_sub(_fee)?.checked_mul(bid_amount.to_f64()?)?;
-    let _bid_reserve = bid_reserve.to_f64()?;
-    let _ask_reserve = ask_reserve.to_f64()?;
-  
-    let balance_ratio = _bid_reserve.checked_div(_bid_reserve.checked_add(_bid_amount)?)?;
-    let weight_ratio = bid_weight.checked_div(ask_weight)?;
-    let ask_amount = 1_f64
-      .checked_sub(balance_ratio.checked_pow(weight_ratio)?)?
-      .checked_mul(_ask_reserve)?;
-  
+) -> Option<u64> {
+    let fee_complement = PRECISION_U128.checked_sub(fee.to_u128()?)?;
+    let _bid_amount = bid_amount
+        .to_u128()?
+        .checked_mul(fee_complement)?
+        .checked_div(PRECISION_U128)?;
+    let _bid_reserve = bid_reserve.to_u128()?;
+    let _ask_reserve = ask_reserve.to_u128()?;
+
+    // Round the power up so that the ask amount rounds down
+    let balance_ratio = fixed_point::div_up(_bid_reserve, _bid_reserve.checked_add(_bid_amount)?)?;
+    let weight_ratio = fixed_point::div_down(bid_weight.to_u128()?, ask_weight.to_u128()?)?;
+    let power = fixed_point::pow_up(balance_ratio, weight_ratio)?;
+    let ask_amount = fixed_point::mul_down(_ask_reserve, ONE.saturating_sub(power))?;
+
     ask_amount.to_u64()
-  }
-  
\ No newline at end of file
+}
+
+pub fn calc_bid_amount_swap(
+    ask_amount: u64,
+    bid_reserve: u64,
+    bid_weight: u64,
+    ask_reserve: u64,
+    ask_weight: u64,
+    fee: u64,
+) -> Option<u64> {
+    if ask_amount >= ask_reserve {
+        return None;
+    }
+    let fee_complement = PRECISION_U128.checked_sub(fee.to_u128()?)?;
+    let _ask_amount = ask_amount.to_u128()?;
+    let _bid_reserve = bid_reserve.to_u128()?;
+    let _ask_reserve = ask_reserve.to_u128()?;
+
+    // Round everything up so that the bid amount always covers the ask amount
+    let balance_ratio = fixed_point::div_up(_ask_reserve, _ask_reserve.checked_sub(_ask_amount)?)?;
+    let weight_ratio = fixed_point::div_up(ask_weight.to_u128()?, bid_weight.to_u128()?)?;
+    let power = fixed_point::pow_up(balance_ratio, weight_ratio)?;
+    let bid_amount = fixed_point::mul_up(_bid_reserve, power.checked_sub(ONE)?)?;
+    let bid_amount =
+        fixed_point::mul_div_up(bid_amount, PRECISION_U128, fee_complement)?.to_u64()?;
+
+    // The forward quote rounds its own power up, so the closed form can be a
+    // few units off either way. Bracket the smallest covering bid between an
+    // amount that falls short and one that covers, then bisect
+    let covers = |bid_amount: u64| -> Option<bool> {
+        Some(
+            calc_ask_amount_swap(
+                bid_amount,
+                bid_reserve,
+                bid_weight,
+                ask_reserve,
+                ask_weight,
+                fee,
+            )? >= ask_amount,
+        )
+    };
+    let mut step = 1_u64;
+    let (mut short, mut covering) = if covers(bid_amount)? {
+        let mut covering = bid_amount;
+        loop {
+            if covering == 0 {
+                return Some(0);
+            }
+            let lower = covering.saturating_sub(step);
+            if !covers(lower)? {
+                break (lower, covering);
+            }
+            covering = lower;
+            step = step.checked_mul(2)?;
+        }
+    } else {
+        let mut short = bid_amount;
+        loop {
+            let higher = short.checked_add(step)?;
+            if covers(higher)? {
+                break (short, higher);
+            }
+            short = higher;
+            step = step.checked_mul(2)?;
+        }
+    };
+    while covering - short > 1 {
+        let middle = short + (covering - short) / 2;
+        if covers(middle)? {
+            covering = middle;
+        } else {
+            short = middle;
+        }
+    }
+    Some(covering)
+}
+
+// Weights as fixed-point fractions of their total
+fn normalize_weights_fixed(weights: &[u64]) -> Option<Vec<u128>> {
+    let total_weight = weights
+        .iter()
+        .try_fold(0_u128, |total, weight| total.checked_add(weight.to_u128()?))?;
+    weights
+        .iter()
+        .map(|weight| fixed_point::mul_div_down(weight.to_u128()?, ONE, total_weight))
+        .collect()
+}
+
+///
+/// Weighted geometric mean of the reserves, prod(B_i ^ w_i) with normalized
+/// weights, as fixed-point. Swaps never lower it, up to the pow error bound.
+///
+pub fn calc_invariant(reserves: Vec<u64>, weights: Vec<u64>) -> Option<u128> {
+    if reserves.is_empty() || reserves.len() != weights.len() {
+        return None;
+    }
+    let normalized_weights = normalize_weights_fixed(&weights)?;
+    let mut invariant = ONE;
+    for (reserve, weight) in reserves.iter().zip(normalized_weights) {
+        let power = fixed_point::pow_down(reserve.to_u128()?.checked_mul(ONE)?, weight)?;
+        invariant = fixed_point::mul_down(invariant, power)?;
+    }
+    Some(invariant)
+}
+
+///
+/// Value of the reserves given the UI price of every mint
+///
+pub fn calc_pool_value(
+    reserves: Vec<u64>,
+    decimals: Vec<u8>,
+    prices: Vec<Decimal>,
+) -> Option<Decimal> {
+    if reserves.len() != decimals.len() || reserves.len() != prices.len() {
+        return None;
+    }
+    let mut pool_value = Decimal::ZERO;
+    for ((reserve, decimals), price) in reserves.iter().zip(decimals).zip(prices) {
+        let ui_reserve = Decimal::from(*reserve)
+            .checked_div(Decimal::from(10_u64.checked_pow(decimals.into())?))?;
+        pool_value = pool_value.checked_add(ui_reserve.checked_mul(price)?)?;
+    }
+    Some(pool_value)
+}
+
+///
+/// UI price of one LP token, which has `MINT_LPT_DECIMALS` decimals
+///
+pub fn calc_lp_token_price(pool_value: Decimal, lpt_supply: u64) -> Option<Decimal> {
+    let ui_lpt_supply = Decimal::from(lpt_supply)
+        .checked_div(Decimal::from(10_u64.checked_pow(MINT_LPT_DECIMALS.into())?))?;
+    pool_value.checked_div(ui_lpt_supply)
+}
+
+///
+/// LP tokens minted by the first deposit of a pool: the weighted geometric
+/// mean of the amounts (the pool invariant) times the number of mints
+///
+pub fn calc_starting_lpt(amounts: Vec<u64>, weights: Vec<u64>) -> Option<u64> {
+    if amounts.is_empty() || amounts.len() != weights.len() || amounts.contains(&0) {
+        return None;
+    }
+    calc_invariant(amounts.clone(), weights)?
+        .checked_mul(amounts.len().to_u128()?)?
+        .checked_div(ONE)?
+        .to_u64()
+}
+
+///
+/// LP tokens minted by a proportional deposit, limited by the mint whose
+/// amount covers the smallest share of its reserve
+///
+pub fn calc_lpt_by_full_side(
+    amounts: Vec<u64>,
+    reserves: Vec<u64>,
+    lpt_supply: u64,
+) -> Option<u64> {
+    if amounts.is_empty() || amounts.len() != reserves.len() {
+        return None;
+    }
+    let mut lpt = u128::MAX;
+    for (amount, reserve) in amounts.iter().zip(reserves) {
+        let share = fixed_point::mul_div_down(
+            amount.to_u128()?,
+            lpt_supply.to_u128()?,
+            reserve.to_u128()?,
+        )?;
+        lpt = lpt.min(share);
+    }
+    lpt.to_u64()
+}
+
+///
+/// LP tokens minted by a deposit of arbitrary amounts.
+///
+/// The part of an amount above the proportional share is implicitly swapped
+/// into the other mints, so it pays the swap fee.
+///
+pub fn calc_lpt_by_side(
+    amounts: Vec<u64>,
+    reserves: Vec<u64>,
+    weights: Vec<u64>,
+    lpt_supply: u64,
+    fee: u64,
+) -> Option<u64> {
+    if amounts.is_empty() || amounts.len() != reserves.len() || amounts.len() != weights.len() {
+        return None;
+    }
+    let normalized_weights = normalize_weights_fixed(&weights)?;
+    let fee = fixed_point::mul_div_up(fee.to_u128()?, ONE, PRECISION_U128)?;
+    let fee_complement = ONE.checked_sub(fee)?;
+
+    // Growth of the pool if the deposit paid no fee
+    let mut balance_ratios = vec![];
+    let mut invariant_ratio_with_fees = 0_u128;
+    for ((amount, reserve), weight) in amounts.iter().zip(&reserves).zip(&normalized_weights) {
+        let reserve = reserve.to_u128()?;
+        let balance_ratio =
+            fixed_point::div_down(reserve.checked_add(amount.to_u128()?)?, reserve)?;
+        invariant_ratio_with_fees = invariant_ratio_with_fees
+            .checked_add(fixed_point::mul_down(balance_ratio, *weight)?)?;
+        balance_ratios.push(balance_ratio);
+    }
+
+    let mut invariant_ratio = ONE;
+    for (((amount, reserve), weight), balance_ratio) in amounts
+        .iter()
+        .zip(&reserves)
+        .zip(&normalized_weights)
+        .zip(balance_ratios)
+    {
+        let amount = amount.to_u128()?;
+        let reserve = reserve.to_u128()?;
+        let amount_without_fee = if balance_ratio > invariant_ratio_with_fees {
+            // The normalized weights round down and can add up to less than
+            // ONE, so a dust deposit can leave the ratio just below ONE
+            let non_taxable_amount =
+                fixed_point::mul_down(reserve, invariant_ratio_with_fees.saturating_sub(ONE))?;
+            let taxable_amount = amount.checked_sub(non_taxable_amount)?;
+            non_taxable_amount
+                .checked_add(fixed_point::mul_down(taxable_amount, fee_complement)?)?
+        } else {
+            amount
+        };
+        let balance_ratio =
+            fixed_point::div_down(reserve.checked_add(amount_without_fee)?, reserve)?;
+        invariant_ratio = fixed_point::mul_down(
+            invariant_ratio,
+            fixed_point::pow_down(balance_ratio, *weight)?,
+        )?;
+    }
+
+    fixed_point::mul_down(lpt_supply.to_u128()?, invariant_ratio.saturating_sub(ONE))?.to_u64()
+}
+
+///
+/// Amounts of every mint returned by burning LP tokens, pro rata to the reserves
+///
+pub fn calc_withdraw_lpt(lpt: u64, reserves: Vec<u64>, lpt_supply: u64) -> Option<Vec<u64>> {
+    if lpt > lpt_supply {
+        return None;
+    }
+    reserves
+        .iter()
+        .map(|reserve| {
+            fixed_point::mul_div_down(reserve.to_u128()?, lpt.to_u128()?, lpt_supply.to_u128()?)?
+                .to_u64()
+        })
+        .collect()
+}
+
+///
+/// Amount of the mint at `mint_idx` returned by burning LP tokens.
+///
+/// Only the proportional share of that mint is free, the rest is implicitly
+/// swapped out of the other mints so it pays the swap fee.
+///
+pub fn calc_withdraw_single(
+    lpt: u64,
+    mint_idx: usize,
+    reserves: Vec<u64>,
+    weights: Vec<u64>,
+    lpt_supply: u64,
+    fee: u64,
+) -> Option<u64> {
+    if lpt >= lpt_supply || reserves.len() != weights.len() {
+        return None;
+    }
+    let reserve = reserves.get(mint_idx)?.to_u128()?;
+    let weight = *normalize_weights_fixed(&weights)?.get(mint_idx)?;
+    let fee = fixed_point::mul_div_up(fee.to_u128()?, ONE, PRECISION_U128)?;
+
+    // Round the remaining reserve up so that the amount out rounds down
+    let lpt_supply = lpt_supply.to_u128()?;
+    let invariant_ratio = fixed_point::div_up(lpt_supply.checked_sub(lpt.to_u128()?)?, lpt_supply)?;
+    let balance_ratio = fixed_point::pow_up(invariant_ratio, fixed_point::div_up(ONE, weight)?)?;
+    let amount_without_fee = fixed_point::mul_down(reserve, ONE.saturating_sub(balance_ratio))?;
+
+    let taxable_amount = fixed_point::mul_up(amount_without_fee, ONE.checked_sub(weight)?)?;
+    let non_taxable_amount = amount_without_fee.saturating_sub(taxable_amount);
+    non_taxable_amount
+        .checked_add(fixed_point::mul_down(
+            taxable_amount,
+            ONE.checked_sub(fee)?,
+        )?)?
+        .to_u64()
+}
+
+///
+/// Marginal price of the ask mint in bid mint units, as fixed-point:
+/// (Bi / Wi) / (Bo / Wo), grossed up by the fee paid on the bid
+///
+pub fn calc_spot_price(
+    bid_reserve: u64,
+    bid_weight: u64,
+    ask_reserve: u64,
+    ask_weight: u64,
+    fee: u64,
+) -> Option<u128> {
+    let numerator = bid_reserve.to_u128()?.checked_mul(ask_weight.to_u128()?)?;
+    let denominator = ask_reserve.to_u128()?.checked_mul(bid_weight.to_u128()?)?;
+    let spot_price = fixed_point::mul_div_down(numerator, ONE, denominator)?;
+    let fee_complement = PRECISION_U128.checked_sub(fee.to_u128()?)?;
+    fixed_point::mul_div_down(spot_price, PRECISION_U128, fee_complement)
+}
+
+///
+/// Average price of a trade in bid mint units per ask mint unit, as fixed-point
+///
+pub fn calc_effective_price(bid_amount: u64, ask_amount: u64) -> Option<u128> {
+    fixed_point::mul_div_down(bid_amount.to_u128()?, ONE, ask_amount.to_u128()?)
+}
+
+///
+/// Converts a fixed-point price between raw amounts into a price between UI
+/// amounts, i.e. scaled by the decimals of both mints
+///
+pub fn fixed_price_to_ui(price: u128, bid_decimals: u8, ask_decimals: u8) -> Option<Decimal> {
+    let price = Decimal::try_from_i128_with_scale(price.to_i128()?, 18).ok()?;
+    let ask_unit = Decimal::from(10_u64.checked_pow(ask_decimals.into())?);
+    let bid_unit = Decimal::from(10_u64.checked_pow(bid_decimals.into())?);
+    price.checked_mul(ask_unit)?.checked_div(bid_unit)
+}
+
+///
+/// Rejects swaps taking more than a share of the reserves, with the max
+/// ratios in PRECISION
+///
+pub fn check_swap_ratios(
+    bid_amount: u64,
+    bid_reserve: u64,
+    ask_amount: u64,
+    ask_reserve: u64,
+    max_in_ratio: u64,
+    max_out_ratio: u64,
+) -> Result<(), ErrorCode> {
+    let max_bid_amount =
+        fixed_point::mul_div_down(bid_reserve.into(), max_in_ratio.into(), PRECISION_U128)
+            .ok_or(ErrorCode::Overflow)?;
+    if u128::from(bid_amount) > max_bid_amount {
+        return Err(ErrorCode::MaxInRatio);
+    }
+    let max_ask_amount =
+        fixed_point::mul_div_down(ask_reserve.into(), max_out_ratio.into(), PRECISION_U128)
+            .ok_or(ErrorCode::Overflow)?;
+    if u128::from(ask_amount) > max_ask_amount {
+        return Err(ErrorCode::MaxOutRatio);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_calc_ask_amount_swap_known_values() {
+    // Equal weights without fee: ask = 1000 - 1000 * 1000 / 1100 = 90.9
+    assert_eq!(calc_ask_amount_swap(100, 1_000, 1, 1_000, 1, 0), Some(90));
+    // 80/20 pool: ask = 10^9 * (1 - (10^9 / 1.1 * 10^9)^4) = 316_986_544.6
+    assert_eq!(
+        calc_ask_amount_swap(100_000_000, 1_000_000_000, 8, 1_000_000_000, 2, 0),
+        Some(316_986_544)
+    );
+    assert_eq!(calc_ask_amount_swap(0, 1_000, 1, 1_000, 1, 0), Some(0));
+}
+
+#[test]
+fn test_calc_ask_amount_swap_monotone() {
+    let mut last = 0;
+    for bid_amount in (0..5_000_000_000_u64).step_by(9_999_991) {
+        let ask_amount = calc_ask_amount_swap(
+            bid_amount,
+            1_234_567_890_123,
+            3,
+            98_765_432_109,
+            7,
+            2_500_000,
+        )
+        .unwrap();
+        assert!(ask_amount >= last);
+        last = ask_amount;
+    }
+}
+
+#[test]
+fn test_calc_bid_amount_swap_round_trip() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(4);
+    for _ in 0..10_000 {
+        let bid_reserve = rng.gen_range(1_000..1_000_000_000_000_000);
+        let ask_reserve = rng.gen_range(1_000..1_000_000_000_000_000);
+        let bid_weight = rng.gen_range(1..50);
+        let ask_weight = rng.gen_range(1..50);
+        let fee = rng.gen_range(0..100_000_000); // up to 10%
+        let ask_amount = rng.gen_range(1..ask_reserve / 2);
+
+        let bid_amount = match calc_bid_amount_swap(
+            ask_amount,
+            bid_reserve,
+            bid_weight,
+            ask_reserve,
+            ask_weight,
+            fee,
+        ) {
+            Some(bid_amount) => bid_amount,
+            // The required input does not fit in u64
+            None => continue,
+        };
+        let received = calc_ask_amount_swap(
+            bid_amount,
+            bid_reserve,
+            bid_weight,
+            ask_reserve,
+            ask_weight,
+            fee,
+        )
+        .unwrap();
+        assert!(
+            received >= ask_amount,
+            "bid {} yields {} < requested {}",
+            bid_amount,
+            received,
+            ask_amount
+        );
+        // And it is the smallest bid that does
+        let received = calc_ask_amount_swap(
+            bid_amount - 1,
+            bid_reserve,
+            bid_weight,
+            ask_reserve,
+            ask_weight,
+            fee,
+        )
+        .unwrap();
+        assert!(
+            received < ask_amount,
+            "bid {} already yields {} >= requested {}",
+            bid_amount - 1,
+            received,
+            ask_amount
+        );
+    }
+}
+
+#[test]
+fn test_calc_bid_amount_swap_drain() {
+    assert_eq!(calc_bid_amount_swap(100, 1_000, 1, 100, 1, 0), None);
+    assert_eq!(calc_bid_amount_swap(101, 1_000, 1, 100, 1, 0), None);
+}
+
+#[test]
+fn test_calc_starting_lpt() {
+    // Equal amounts: the invariant is the amount itself
+    let lpt = calc_starting_lpt(vec![1_000_000_000, 1_000_000_000], vec![1, 1]).unwrap();
+    assert!((1_999_999_990..=2_000_000_000).contains(&lpt), "{}", lpt);
+    // 80/20 pool: 2 * (10^12)^0.8 * (10^8)^0.2 = 316_978_638_492.9
+    let lpt = calc_starting_lpt(vec![1_000_000_000_000, 100_000_000], vec![8, 2]).unwrap();
+    assert!(
+        (316_978_638_000..=316_978_638_493).contains(&lpt),
+        "{}",
+        lpt
+    );
+    assert_eq!(calc_starting_lpt(vec![0, 1_000], vec![1, 1]), None);
+    assert_eq!(calc_starting_lpt(vec![1_000], vec![1, 1]), None);
+}
+
+#[test]
+fn test_calc_lpt_by_full_side() {
+    let reserves = vec![1_000_000_000, 4_000_000_000];
+    // 10% of every reserve mints 10% of the supply
+    assert_eq!(
+        calc_lpt_by_full_side(vec![100_000_000, 400_000_000], reserves.clone(), 5_000_000),
+        Some(500_000)
+    );
+    // The extra amount of the second mint is not counted
+    assert_eq!(
+        calc_lpt_by_full_side(vec![100_000_000, 900_000_000], reserves.clone(), 5_000_000),
+        Some(500_000)
+    );
+    assert_eq!(
+        calc_lpt_by_full_side(vec![100_000_000], reserves, 5_000_000),
+        None
+    );
+}
+
+#[test]
+fn test_calc_lpt_by_side() {
+    let reserves = vec![1_000_000_000, 1_000_000_000];
+    let weights = vec![1, 1];
+    // Without fee the pool grows by sqrt(1.21) = 1.1
+    let lpt = calc_lpt_by_side(
+        vec![210_000_000, 0],
+        reserves.clone(),
+        weights.clone(),
+        1_000_000_000,
+        0,
+    )
+    .unwrap();
+    assert!((99_999_990..=100_000_000).contains(&lpt), "{}", lpt);
+    // The fee applies to the part swapped into the other mint
+    let lpt_with_fee = calc_lpt_by_side(
+        vec![210_000_000, 0],
+        reserves.clone(),
+        weights.clone(),
+        1_000_000_000,
+        2_500_000,
+    )
+    .unwrap();
+    assert!(lpt_with_fee < lpt);
+    // A proportional deposit pays no fee and matches the full-side deposit
+    let full_side = calc_lpt_by_full_side(
+        vec![100_000_000, 100_000_000],
+        reserves.clone(),
+        1_000_000_000,
+    )
+    .unwrap();
+    let side = calc_lpt_by_side(
+        vec![100_000_000, 100_000_000],
+        reserves,
+        weights,
+        1_000_000_000,
+        2_500_000,
+    )
+    .unwrap();
+    assert!(
+        side <= full_side && full_side - side <= 10,
+        "{} vs {}",
+        side,
+        full_side
+    );
+}
+
+#[test]
+fn test_calc_lpt_by_side_dust() {
+    // Three equal weights normalize to ONE - 1 in total
+    let reserves = vec![1_000_000_000, 2_000_000_000, 3_000_000_000];
+    let weights = vec![1, 1, 1];
+    assert!(
+        normalize_weights_fixed(&weights)
+            .unwrap()
+            .iter()
+            .sum::<u128>()
+            < ONE
+    );
+    for amounts in [vec![0, 0, 0], vec![1, 0, 0]] {
+        assert_eq!(
+            calc_lpt_by_side(
+                amounts,
+                reserves.clone(),
+                weights.clone(),
+                1_000_000_000,
+                2_500_000,
+            ),
+            Some(0)
+        );
+    }
+}
+
+#[test]
+fn test_calc_lpt_by_side_covers_full_side() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(5);
+    for _ in 0..1_000 {
+        let reserves: Vec<u64> = (0..3)
+            .map(|_| rng.gen_range(1_000_000..1_000_000_000_000))
+            .collect();
+        let weights: Vec<u64> = (0..3).map(|_| rng.gen_range(1..100)).collect();
+        let amounts: Vec<u64> = reserves
+            .iter()
+            .map(|reserve| rng.gen_range(0..reserve / 2))
+            .collect();
+        let lpt_supply = rng.gen_range(1_000_000..1_000_000_000_000);
+        let fee = rng.gen_range(0..10_000_000);
+
+        // Amounts above the proportional share can only add LP tokens
+        let lpt = calc_lpt_by_side(
+            amounts.clone(),
+            reserves.clone(),
+            weights.clone(),
+            lpt_supply,
+            fee,
+        )
+        .unwrap();
+        let proportional =
+            calc_lpt_by_full_side(amounts.clone(), reserves.clone(), lpt_supply).unwrap();
+        assert!(lpt + 1 >= proportional, "{} < {}", lpt, proportional);
+    }
+}
+
+#[test]
+fn test_calc_withdraw_lpt() {
+    assert_eq!(
+        calc_withdraw_lpt(500_000, vec![1_000_000_000, 4_000_000_001], 5_000_000),
+        Some(vec![100_000_000, 400_000_000])
+    );
+    assert_eq!(
+        calc_withdraw_lpt(5_000_000, vec![1_000, 4_000], 5_000_000),
+        Some(vec![1_000, 4_000])
+    );
+    assert_eq!(calc_withdraw_lpt(5_000_001, vec![1_000], 5_000_000), None);
+}
+
+#[test]
+fn test_calc_withdraw_single() {
+    let reserves = vec![1_000_000_000, 1_000_000_000];
+    let weights = vec![1, 1];
+    // Without fee: burning 10% leaves 0.9^2 = 81% of the reserve
+    let amount = calc_withdraw_single(
+        100_000_000,
+        0,
+        reserves.clone(),
+        weights.clone(),
+        1_000_000_000,
+        0,
+    )
+    .unwrap();
+    assert!((189_999_990..=190_000_000).contains(&amount), "{}", amount);
+    let amount_with_fee = calc_withdraw_single(
+        100_000_000,
+        0,
+        reserves.clone(),
+        weights.clone(),
+        1_000_000_000,
+        2_500_000,
+    )
+    .unwrap();
+    assert!(amount_with_fee < amount);
+    assert_eq!(
+        calc_withdraw_single(
+            1_000_000_000,
+            0,
+            reserves.clone(),
+            weights.clone(),
+            1_000_000_000,
+            0
+        ),
+        None
+    );
+    assert_eq!(
+        calc_withdraw_single(1, 2, reserves, weights, 1_000_000_000, 0),
+        None
+    );
+}
+
+#[test]
+fn test_deposit_then_withdraw_never_profits() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(6);
+    for _ in 0..1_000 {
+        let reserves: Vec<u64> = (0..3)
+            .map(|_| rng.gen_range(1_000_000..1_000_000_000_000))
+            .collect();
+        let weights: Vec<u64> = (0..3).map(|_| rng.gen_range(1..100)).collect();
+        let lpt_supply = rng.gen_range(1_000_000..1_000_000_000_000);
+        let fee = rng.gen_range(0..10_000_000);
+
+        // Full-side deposit, proportional withdrawal
+        let amounts: Vec<u64> = reserves
+            .iter()
+            .map(|reserve| rng.gen_range(0..reserve / 2))
+            .collect();
+        let lpt = calc_lpt_by_full_side(amounts.clone(), reserves.clone(), lpt_supply).unwrap();
+        let new_reserves: Vec<u64> = reserves.iter().zip(&amounts).map(|(r, a)| r + a).collect();
+        let withdrawn = calc_withdraw_lpt(lpt, new_reserves, lpt_supply + lpt).unwrap();
+        for (withdrawn, amount) in withdrawn.iter().zip(&amounts) {
+            assert!(withdrawn <= amount, "{} > {}", withdrawn, amount);
+        }
+
+        // Single-side deposit, single-side withdrawal of the same mint
+        let mint_idx = rng.gen_range(0..3);
+        let mut amounts = vec![0; 3];
+        amounts[mint_idx] = rng.gen_range(1..reserves[mint_idx] / 2);
+        let lpt = calc_lpt_by_side(
+            amounts.clone(),
+            reserves.clone(),
+            weights.clone(),
+            lpt_supply,
+            fee,
+        )
+        .unwrap();
+        let mut new_reserves = reserves.clone();
+        new_reserves[mint_idx] += amounts[mint_idx];
+        let withdrawn =
+            calc_withdraw_single(lpt, mint_idx, new_reserves, weights, lpt_supply + lpt, fee)
+                .unwrap();
+        assert!(
+            withdrawn <= amounts[mint_idx],
+            "{} > {}",
+            withdrawn,
+            amounts[mint_idx]
+        );
+    }
+}
+
+#[test]
+fn test_calc_spot_price() {
+    // 80/20 pool with equal reserves: an ask unit costs 4 bid units
+    assert_eq!(
+        calc_spot_price(1_000_000, 2, 1_000_000, 8, 0),
+        Some(4 * ONE)
+    );
+    // The 0.25% fee is paid on top: 1 / (1 - 0.0025)
+    assert_eq!(
+        calc_spot_price(1_000_000, 1, 1_000_000, 1, 2_500_000),
+        Some(1_002_506_265_664_160_401)
+    );
+    assert_eq!(calc_spot_price(1_000_000, 1, 0, 1, 0), None);
+
+    // Small trades execute at about the spot price, larger ones pay more
+    let spot_price = calc_spot_price(1_000_000_000, 1, 2_000_000_000, 1, 0).unwrap();
+    let small_ask = calc_ask_amount_swap(1_000, 1_000_000_000, 1, 2_000_000_000, 1, 0).unwrap();
+    let large_ask =
+        calc_ask_amount_swap(100_000_000, 1_000_000_000, 1, 2_000_000_000, 1, 0).unwrap();
+    let small_price = calc_effective_price(1_000, small_ask).unwrap();
+    let large_price = calc_effective_price(100_000_000, large_ask).unwrap();
+    assert!(spot_price <= small_price && small_price < large_price);
+    assert!(small_price - spot_price < ONE / 1_000);
+    assert_eq!(calc_effective_price(1_000, 0), None);
+}
+
+#[test]
+fn test_fixed_price_to_ui() {
+    // 1 raw SOL unit (9 decimals) for 0.02 raw USDC units (6 decimals)
+    // is 20 USDC per SOL
+    assert_eq!(fixed_price_to_ui(ONE / 50, 6, 9), Some(Decimal::new(20, 0)));
+    assert_eq!(fixed_price_to_ui(3 * ONE, 9, 9), Some(Decimal::new(3, 0)));
+    assert_eq!(fixed_price_to_ui(u128::MAX, 9, 9), None);
+}
+
+#[test]
+fn test_calc_invariant() {
+    // Equal reserves: the invariant is the reserve itself
+    let invariant = calc_invariant(vec![1_000_000, 1_000_000, 1_000_000], vec![1, 2, 3]).unwrap();
+    assert!(invariant.abs_diff(1_000_000 * ONE) < 1_000_000 * ONE / 10_000_000_000_000);
+    // 80/20 pool: (10^12)^0.8 * (10^8)^0.2 = 158_489_319_246.4
+    let invariant = calc_invariant(vec![1_000_000_000_000, 100_000_000], vec![8, 2]).unwrap();
+    assert_eq!(invariant / ONE, 158_489_319_246);
+    assert_eq!(calc_invariant(vec![1_000], vec![1, 1]), None);
+}
+
+#[test]
+fn test_swap_never_lowers_invariant() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(7);
+    for _ in 0..1_000 {
+        let bid_reserve = rng.gen_range(1_000_000..1_000_000_000_000_000);
+        let ask_reserve = rng.gen_range(1_000_000..1_000_000_000_000_000);
+        let bid_weight = rng.gen_range(1..50);
+        let ask_weight = rng.gen_range(1..50);
+        let fee = rng.gen_range(0..10_000_000);
+        let bid_amount = rng.gen_range(0..bid_reserve);
+        let ask_amount = calc_ask_amount_swap(
+            bid_amount,
+            bid_reserve,
+            bid_weight,
+            ask_reserve,
+            ask_weight,
+            fee,
+        )
+        .unwrap();
+
+        let weights = vec![bid_weight, ask_weight];
+        let before = calc_invariant(vec![bid_reserve, ask_reserve], weights.clone()).unwrap();
+        let after = calc_invariant(
+            vec![bid_reserve + bid_amount, ask_reserve - ask_amount],
+            weights,
+        )
+        .unwrap();
+        // Both sides carry the pow error bound of every factor
+        let tolerance =
+            fixed_point::mul_up(before, 4 * fixed_point::MAX_POW_RELATIVE_ERROR).unwrap();
+        assert!(
+            after + tolerance >= before,
+            "invariant {} -> {} swapping {} for {}",
+            before,
+            after,
+            bid_amount,
+            ask_amount
+        );
+    }
+}
+
+#[test]
+fn test_calc_pool_value_and_lp_token_price() {
+    // 1_000 USDC at $1 and 10 SOL at $100
+    let pool_value = calc_pool_value(
+        vec![1_000_000_000, 10_000_000_000],
+        vec![6, 9],
+        vec![Decimal::ONE, Decimal::new(100, 0)],
+    )
+    .unwrap();
+    assert_eq!(pool_value, Decimal::new(2_000, 0));
+    // 400 LP tokens
+    assert_eq!(
+        calc_lp_token_price(pool_value, 400_000_000_000),
+        Some(Decimal::new(5, 0))
+    );
+    assert_eq!(calc_lp_token_price(pool_value, 0), None);
+    assert_eq!(
+        calc_pool_value(vec![1], vec![6, 9], vec![Decimal::ONE]),
+        None
+    );
+}
+
+#[test]
+fn test_check_swap_ratios() {
+    let check = |bid_amount, ask_amount| {
+        check_swap_ratios(
+            bid_amount,
+            1_000_000,
+            ask_amount,
+            2_000_000,
+            MAX_IN_RATIO,
+            MAX_OUT_RATIO,
+        )
+    };
+    assert!(check(300_000, 600_000).is_ok());
+    assert!(matches!(check(300_001, 0), Err(ErrorCode::MaxInRatio)));
+    assert!(matches!(check(0, 600_001), Err(ErrorCode::MaxOutRatio)));
+    // Ratios of PRECISION allow the whole reserve
+    assert!(check_swap_ratios(
+        1_000_000,
+        1_000_000,
+        2_000_000,
+        2_000_000,
+        PRECISION_U64,
+        PRECISION_U64
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_calc_lbp_weight() {
+    let weight = |timestamp| calc_lbp_weight(900, 100, 1_000, 2_000, timestamp).unwrap();
+    assert_eq!(weight(0), 900);
+    assert_eq!(weight(1_000), 900);
+    assert_eq!(weight(1_250), 700);
+    assert_eq!(weight(1_500), 500);
+    assert_eq!(weight(2_000), 100);
+    assert_eq!(weight(i64::MAX), 100);
+    // Rising weights and an empty window
+    assert_eq!(calc_lbp_weight(100, 900, 1_000, 2_000, 1_750), Some(700));
+    assert_eq!(calc_lbp_weight(100, 900, 1_000, 1_000, 1_000), Some(100));
+    assert_eq!(calc_lbp_weight(100, 900, 1_000, 1_000, 1_001), Some(900));
+    // Extreme bounds must not overflow
+    assert_eq!(
+        calc_lbp_weight(u64::MAX, 0, i64::MIN, i64::MAX, 0),
+        Some(u64::MAX / 2)
+    );
+}
@@ -0,0 +1,230 @@
+use crate::constant::*;
+use crate::f64_trait::F64Trait;
+use num_traits::ToPrimitive;
+
+//
+// Floating point versions of the weighted-pool formulas, kept to compare
+// against the fixed-point oracle
+//
+
+pub fn calc_ask_amount_swap(
+    bid_amount: u64,
+    bid_reserve: u64,
+    bid_weight: f64,
+    ask_reserve: u64,
+    ask_weight: f64,
+    fee: u64,
+) -> Option<u64> {
+    let _fee = fee.to_f64()?.checked_div(PRECISION_F64)?;
+    let _bid_amount = 1_f64.checked_sub(_fee)?.checked_mul(bid_amount.to_f64()?)?;
+    let _bid_reserve = bid_reserve.to_f64()?;
+    let _ask_reserve = ask_reserve.to_f64()?;
+
+    let balance_ratio = _bid_reserve.checked_div(_bid_reserve.checked_add(_bid_amount)?)?;
+    let weight_ratio = bid_weight.checked_div(ask_weight)?;
+    let ask_amount = 1_f64
+        .checked_sub(balance_ratio.checked_pow(weight_ratio)?)?
+        .checked_mul(_ask_reserve)?;
+
+    ask_amount.to_u64()
+}
+
+pub fn calc_bid_amount_swap(
+    ask_amount: u64,
+    bid_reserve: u64,
+    bid_weight: f64,
+    ask_reserve: u64,
+    ask_weight: f64,
+    fee: u64,
+) -> Option<u64> {
+    if ask_amount >= ask_reserve {
+        return None;
+    }
+    let _fee = fee.to_f64()?.checked_div(PRECISION_F64)?;
+    let _ask_amount = ask_amount.to_f64()?;
+    let _bid_reserve = bid_reserve.to_f64()?;
+    let _ask_reserve = ask_reserve.to_f64()?;
+
+    let balance_ratio = _ask_reserve.checked_div(_ask_reserve.checked_sub(_ask_amount)?)?;
+    let weight_ratio = ask_weight.checked_div(bid_weight)?;
+    let bid_amount = balance_ratio
+        .checked_pow(weight_ratio)?
+        .checked_sub(1_f64)?
+        .checked_mul(_bid_reserve)?
+        .checked_div(1_f64.checked_sub(_fee)?)?;
+    let mut bid_amount = bid_amount.ceil().to_u64()?;
+
+    // Float rounding may leave the forward quote a few units short, so keep
+    // growing the bid until it covers the requested ask amount
+    let mut step = 1_u64;
+    while calc_ask_amount_swap(
+        bid_amount,
+        bid_reserve,
+        bid_weight,
+        ask_reserve,
+        ask_weight,
+        fee,
+    )? < ask_amount
+    {
+        bid_amount = bid_amount.checked_add(step)?;
+        step = step.checked_mul(2)?;
+    }
+    Some(bid_amount)
+}
+
+#[test]
+fn test_calc_bid_amount_swap_round_trip() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..10_000 {
+        let bid_reserve = rng.gen_range(1_000..1_000_000_000_000_000);
+        let ask_reserve = rng.gen_range(1_000..1_000_000_000_000_000);
+        let bid_weight = rng.gen_range(0.02..0.98);
+        let ask_weight = rng.gen_range(0.02..0.98);
+        let fee = rng.gen_range(0..100_000_000); // up to 10%
+        let ask_amount = rng.gen_range(1..ask_reserve / 2);
+
+        let bid_amount = match calc_bid_amount_swap(
+            ask_amount,
+            bid_reserve,
+            bid_weight,
+            ask_reserve,
+            ask_weight,
+            fee,
+        ) {
+            Some(bid_amount) => bid_amount,
+            // The required input does not fit in u64
+            None => continue,
+        };
+        let received = calc_ask_amount_swap(
+            bid_amount,
+            bid_reserve,
+            bid_weight,
+            ask_reserve,
+            ask_weight,
+            fee,
+        )
+        .unwrap();
+        assert!(
+            received >= ask_amount,
+            "bid {} yields {} < requested {}",
+            bid_amount,
+            received,
+            ask_amount
+        );
+    }
+}
+
+#[test]
+fn test_calc_bid_amount_swap_drain() {
+    assert_eq!(calc_bid_amount_swap(100, 1_000, 0.5, 100, 0.5, 0), None);
+    assert_eq!(calc_bid_amount_swap(101, 1_000, 0.5, 100, 0.5, 0), None);
+}
+
+#[test]
+fn test_fixed_point_matches_f64() {
+    use crate::oracle;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..10_000 {
+        let bid_reserve = rng.gen_range(1_000_000..1_000_000_000_000_000);
+        let ask_reserve = rng.gen_range(1_000_000..1_000_000_000_000_000);
+        let bid_weight = rng.gen_range(1..50);
+        let ask_weight = rng.gen_range(1..50);
+        let total_weight = (bid_weight + ask_weight) as f64;
+        let fee = rng.gen_range(0..100_000_000);
+        let bid_amount = rng.gen_range(1..bid_reserve);
+
+        let fixed = oracle::calc_ask_amount_swap(
+            bid_amount,
+            bid_reserve,
+            bid_weight,
+            ask_reserve,
+            ask_weight,
+            fee,
+        )
+        .unwrap();
+        let float = calc_ask_amount_swap(
+            bid_amount,
+            bid_reserve,
+            bid_weight as f64 / total_weight,
+            ask_reserve,
+            ask_weight as f64 / total_weight,
+            fee,
+        )
+        .unwrap();
+        let tolerance = (float as f64 * 1e-9).max(2.0);
+        assert!(fixed <= float, "fixed {} > f64 {}", fixed, float);
+        assert!(
+            (fixed as f64 - float as f64).abs() <= tolerance,
+            "fixed {} vs f64 {}",
+            fixed,
+            float
+        );
+    }
+}
+
+#[test]
+fn test_fixed_point_never_quotes_above_f64() {
+    use crate::oracle;
+
+    // The deployed program pays out the f64 amount, a fixed-point quote above
+    // it would fail the swap with `Slippage`
+    let reserves = [
+        1_000_000,
+        1_000_000_000,
+        1_000_000_000_000,
+        1_000_000_000_000_000,
+        1_000_000_000_000_000_000,
+    ];
+    let weights = [1, 2, 3, 5, 9];
+    // Bid amounts in millionths of the bid reserve
+    let bid_ratios = [1, 1_000, 10_000, 100_000, 500_000, 1_000_000, 2_000_000];
+    let fees = [0, 2_500_000, 100_000_000];
+    for bid_reserve in reserves {
+        for ask_reserve in reserves {
+            for bid_weight in weights {
+                for ask_weight in weights {
+                    let total_weight = (bid_weight + ask_weight) as f64;
+                    for bid_ratio in bid_ratios {
+                        let bid_amount = (bid_reserve as u128 * bid_ratio / 1_000_000) as u64;
+                        for fee in fees {
+                            let fixed = oracle::calc_ask_amount_swap(
+                                bid_amount,
+                                bid_reserve,
+                                bid_weight,
+                                ask_reserve,
+                                ask_weight,
+                                fee,
+                            )
+                            .unwrap();
+                            let float = calc_ask_amount_swap(
+                                bid_amount,
+                                bid_reserve,
+                                bid_weight as f64 / total_weight,
+                                ask_reserve,
+                                ask_weight as f64 / total_weight,
+                                fee,
+                            )
+                            .unwrap();
+                            assert!(
+                                fixed <= float,
+                                "fixed {} > f64 {} for bid {} on reserves {}/{} weights {}/{} fee {}",
+                                fixed,
+                                float,
+                                bid_amount,
+                                bid_reserve,
+                                ask_reserve,
+                                bid_weight,
+                                ask_weight,
+                                fee
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
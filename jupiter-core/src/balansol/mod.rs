@@ -12,5 +12,23 @@ pub use math::*;
 pub mod schema;
 pub use schema::*;
 
+// The on-chain swap instruction, only built by the program tests so that the
+// quoting library does not export an entrypoint
+#[cfg(feature = "program")]
+pub mod instructions;
+#[cfg(feature = "program")]
+pub use instructions::*;
+
 pub mod balansol_amm;
 pub use balansol_amm::*;
+
+pub mod events;
+pub use events::*;
+
+anchor_lang::declare_id!("6SRa2Kc3G4wTG319G4Se6yrRWeS1A1Hj79BC3o7X9v6T");
+
+#[cfg(feature = "program")]
+pub mod program;
+
+pub mod discovery;
+pub use discovery::*;
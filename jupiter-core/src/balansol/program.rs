@@ -0,0 +1,17 @@
+use super::*;
+use anchor_lang::prelude::*;
+
+#[program]
+#[allow(clippy::result_large_err)]
+pub mod balansol_program {
+  use super::*;
+
+  pub fn swap<'info>(
+    ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+    bid_amount: u64,
+    limit: u64,
+  ) -> Result<()> {
+    Swap::invoke(ctx, bid_amount, limit)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,91 @@
+use crate::constant::*;
+use crate::oracle::*;
+use crate::pool::{MintActionState, Pool, PoolState};
+use crate::pool_trait::*;
+use anchor_lang::prelude::*;
+
+///
+/// Liquidity bootstrapping pool, a weighted pool whose weights move linearly
+/// from `start_weights` to `end_weights` between `start_time` and `end_time`
+///
+#[account]
+pub struct LbpPool {
+  pub authority: Pubkey,
+  pub fee: u64,
+  pub tax: u64,
+  pub state: PoolState,
+  pub mint_lpt: Pubkey,
+  pub taxman: Pubkey,
+  pub mints: Vec<Pubkey>,
+  pub actions: Vec<MintActionState>,
+  pub treasuries: Vec<Pubkey>,
+  pub reserves: Vec<u64>,
+  pub start_weights: Vec<u64>,
+  pub end_weights: Vec<u64>,
+  pub start_time: i64,
+  pub end_time: i64,
+}
+const VECTOR_IN_LBP_POOL: usize = 6;
+
+impl LbpPool {
+  pub const LEN: usize = ACCOUNT_DISCRIMINATOR
+    + PUBKEY_SIZE
+    + U64_SIZE
+    + U64_SIZE
+    + U8_SIZE
+    + PUBKEY_SIZE
+    + PUBKEY_SIZE
+    + MAXIMUM_MINT_NUMBER * (PUBKEY_SIZE + U8_SIZE + PUBKEY_SIZE + U64_SIZE + U64_SIZE + U64_SIZE)
+    + VECTOR_SIZE * VECTOR_IN_LBP_POOL
+    + I64_SIZE
+    + I64_SIZE;
+
+  ///
+  /// The pool as a static weighted pool at `timestamp`, so that every swap
+  /// and liquidity formula of `Pool` applies
+  ///
+  pub fn to_pool(&self, timestamp: i64) -> Option<Pool> {
+    Some(Pool {
+      authority: self.authority,
+      fee: self.fee,
+      tax: self.tax,
+      state: self.state,
+      mint_lpt: self.mint_lpt,
+      taxman: self.taxman,
+      mints: self.mints.clone(),
+      actions: self.actions.clone(),
+      treasuries: self.treasuries.clone(),
+      reserves: self.reserves.clone(),
+      weights: self.get_weights(timestamp)?,
+    })
+  }
+}
+
+///
+/// Schedule trait
+///
+impl Schedule for LbpPool {
+  fn get_weights(&self, timestamp: i64) -> Option<Vec<u64>> {
+    if self.start_weights.len() != self.end_weights.len() {
+      return None;
+    }
+    self
+      .start_weights
+      .iter()
+      .zip(&self.end_weights)
+      .map(|(start_weight, end_weight)| {
+        calc_lbp_weight(
+          *start_weight,
+          *end_weight,
+          self.start_time,
+          self.end_time,
+          timestamp,
+        )
+      })
+      .collect()
+  }
+  fn get_normalized_weight(&self, mint: Pubkey, timestamp: i64) -> Option<f64> {
+    let mint_idx = self.mints.iter().position(|m| *m == mint)?;
+    normalize_weight(mint_idx, self.get_weights(timestamp)?)
+  }
+}
@@ -1,5 +1,8 @@
 pub mod pool;
 pub use pool::*;
 
+pub mod lbp_pool;
+pub use lbp_pool::*;
+
 pub mod pool_trait;
 pub use pool_trait::*;
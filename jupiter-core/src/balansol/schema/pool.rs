@@ -1,8 +1,12 @@
 use crate::constant::*;
+use crate::errors::ErrorCode;
+use crate::events::SwapEvent;
 use crate::oracle::*;
 use crate::pool_trait::*;
 use anchor_lang::prelude::*;
 use anchor_spl::token;
+use num_traits::ToPrimitive;
+use rust_decimal::Decimal;
 
 ///
 /// Pool state
@@ -44,6 +48,26 @@ pub struct PoolValidation {
   pub pool_states: Vec<PoolState>,
 }
 
+///
+/// One swap of a batch
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchSwapStep {
+  pub bid_mint: Pubkey,
+  pub ask_mint: Pubkey,
+  pub bid_amount: u64,
+}
+
+///
+/// Outcome of a batch, the amount received by every step once taxed and the
+/// reserves once all of them are applied
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchSwapResult {
+  pub ask_amounts: Vec<u64>,
+  pub reserves: Vec<u64>,
+}
+
 #[account]
 pub struct Pool {
   pub authority: Pubkey,
@@ -70,9 +94,46 @@ impl Pool {
     + PUBKEY_SIZE
     + MAXIMUM_MINT_NUMBER * (PUBKEY_SIZE + U8_SIZE + PUBKEY_SIZE + U64_SIZE + U64_SIZE)
     + VECTOR_SIZE * VECTOR_IN_POOL;
-  
+
+  ///
+  /// Swaps `bid_amount` against `reserves` as the swap instruction does, and
+  /// returns the amount received once the taxman cut has left the ask treasury
+  ///
+  fn swap_reserves(
+    &self,
+    reserves: &mut [u64],
+    bid_mint_idx: usize,
+    ask_mint_idx: usize,
+    bid_amount: u64,
+    total_fee: u64,
+  ) -> Option<u64> {
+    if bid_mint_idx == ask_mint_idx {
+      return None;
+    }
+    let ask_amount = calc_ask_amount_swap(
+      bid_amount,
+      reserves[bid_mint_idx],
+      self.weights[bid_mint_idx],
+      reserves[ask_mint_idx],
+      self.weights[ask_mint_idx],
+      total_fee,
+    )?;
+    let tax_amount = ask_amount
+      .to_u128()?
+      .checked_mul(self.tax.into())?
+      .checked_div(PRECISION_U128)?
+      .to_u64()?;
+    let return_amount = ask_amount.checked_sub(tax_amount)?;
+
+    // Both the user and the taxman are paid from the ask treasury, the fee
+    // stays in the pool
+    let bid_reserve = reserves[bid_mint_idx].checked_add(bid_amount)?;
+    let ask_reserve = reserves[ask_mint_idx].checked_sub(ask_amount)?;
+    reserves[bid_mint_idx] = bid_reserve;
+    reserves[ask_mint_idx] = ask_reserve;
+    Some(return_amount)
+  }
 }
-    
 
 ///
 /// Operation trait
@@ -103,6 +164,18 @@ impl Operation for Pool {
     }
     return false;
   }
+  fn can_bid(&self, mint_idx: usize) -> bool {
+    self.valid_mint_states(
+      mint_idx,
+      vec![MintActionState::Active, MintActionState::BidOnly],
+    )
+  }
+  fn can_ask(&self, mint_idx: usize) -> bool {
+    self.valid_mint_states(
+      mint_idx,
+      vec![MintActionState::Active, MintActionState::AskOnly],
+    )
+  }
 }
 
 ///
@@ -135,6 +208,17 @@ impl Accessor for Pool {
     }
     return None;
   }
+  fn apply_swap_event(&mut self, event: &SwapEvent) -> Option<()> {
+    let bid_mint_idx = self.get_mint_index(event.bid_mint)?;
+    let ask_mint_idx = self.get_mint_index(event.ask_mint)?;
+    // The event reports the amount the user received, the tax left the treasury too
+    let ask_amount = event.ask_amount.checked_add(event.total_tax_fee_amount)?;
+    let bid_reserve = self.reserves[bid_mint_idx].checked_add(event.bid_amount)?;
+    let ask_reserve = self.reserves[ask_mint_idx].checked_sub(ask_amount)?;
+    self.reserves[bid_mint_idx] = bid_reserve;
+    self.reserves[ask_mint_idx] = ask_reserve;
+    Some(())
+  }
   fn get_treasury_index(&self, treasury: Pubkey) -> Option<usize> {
     for idx in 0..self.mints.len() {
       if self.treasuries[idx] == treasury {
@@ -159,9 +243,9 @@ impl Exchange for Pool {
     let bid_mint_idx = self.get_mint_index(bid_mint)?;
     let ask_mint_idx = self.get_mint_index(ask_mint)?;
     let bid_reserve = self.reserves[bid_mint_idx];
-    let bid_weight = self.get_normalized_weight(bid_mint)?;
+    let bid_weight = self.weights[bid_mint_idx];
     let ask_reserve = self.reserves[ask_mint_idx];
-    let ask_weight = self.get_normalized_weight(ask_mint)?;
+    let ask_weight = self.weights[ask_mint_idx];
 
     calc_ask_amount_swap(
       bid_amount,
@@ -172,4 +256,327 @@ impl Exchange for Pool {
       total_fee,
     )
   }
+
+  fn calc_bid_amount_swap(
+    &self,
+    ask_amount: u64,
+    bid_mint: Pubkey,
+    ask_mint: Pubkey,
+    total_fee: u64,
+  ) -> Option<u64> {
+    let bid_mint_idx = self.get_mint_index(bid_mint)?;
+    let ask_mint_idx = self.get_mint_index(ask_mint)?;
+    let bid_reserve = self.reserves[bid_mint_idx];
+    let bid_weight = self.weights[bid_mint_idx];
+    let ask_reserve = self.reserves[ask_mint_idx];
+    let ask_weight = self.weights[ask_mint_idx];
+
+    calc_bid_amount_swap(
+      ask_amount,
+      bid_reserve,
+      bid_weight,
+      ask_reserve,
+      ask_weight,
+      total_fee,
+    )
+  }
+
+  fn check_swap_ratios(
+    &self,
+    bid_amount: u64,
+    bid_mint: Pubkey,
+    ask_amount: u64,
+    ask_mint: Pubkey,
+    max_in_ratio: u64,
+    max_out_ratio: u64,
+  ) -> std::result::Result<(), ErrorCode> {
+    let bid_mint_idx = self
+      .get_mint_index(bid_mint)
+      .ok_or(ErrorCode::AccountMint)?;
+    let ask_mint_idx = self
+      .get_mint_index(ask_mint)
+      .ok_or(ErrorCode::AccountMint)?;
+
+    check_swap_ratios(
+      bid_amount,
+      self.reserves[bid_mint_idx],
+      ask_amount,
+      self.reserves[ask_mint_idx],
+      max_in_ratio,
+      max_out_ratio,
+    )
+  }
+
+  fn calc_batch_swap(&self, steps: Vec<BatchSwapStep>, total_fee: u64) -> Option<BatchSwapResult> {
+    // Same checks as a single swap
+    if !self.is_active() {
+      return None;
+    }
+    let mut reserves = self.reserves.clone();
+    let mut ask_amounts = vec![];
+    for step in steps {
+      let bid_mint_idx = self.get_mint_index(step.bid_mint)?;
+      let ask_mint_idx = self.get_mint_index(step.ask_mint)?;
+      if !self.can_bid(bid_mint_idx) || !self.can_ask(ask_mint_idx) {
+        return None;
+      }
+      let ask_amount = self.swap_reserves(
+        &mut reserves,
+        bid_mint_idx,
+        ask_mint_idx,
+        step.bid_amount,
+        total_fee,
+      )?;
+      ask_amounts.push(ask_amount);
+    }
+    Some(BatchSwapResult {
+      ask_amounts,
+      reserves,
+    })
+  }
+
+  fn apply_swap(&mut self, bid_mint: Pubkey, ask_mint: Pubkey, bid_amount: u64) -> Option<u64> {
+    let bid_mint_idx = self.get_mint_index(bid_mint)?;
+    let ask_mint_idx = self.get_mint_index(ask_mint)?;
+    let mut reserves = self.reserves.clone();
+    let return_amount = self.swap_reserves(
+      &mut reserves,
+      bid_mint_idx,
+      ask_mint_idx,
+      bid_amount,
+      self.fee,
+    )?;
+    self.reserves = reserves;
+    Some(return_amount)
+  }
+
+  fn spot_price(&self, bid_mint: Pubkey, ask_mint: Pubkey, total_fee: u64) -> Option<u128> {
+    let bid_mint_idx = self.get_mint_index(bid_mint)?;
+    let ask_mint_idx = self.get_mint_index(ask_mint)?;
+
+    calc_spot_price(
+      self.reserves[bid_mint_idx],
+      self.weights[bid_mint_idx],
+      self.reserves[ask_mint_idx],
+      self.weights[ask_mint_idx],
+      total_fee,
+    )
+  }
+
+  fn effective_price(
+    &self,
+    bid_amount: u64,
+    bid_mint: Pubkey,
+    ask_mint: Pubkey,
+    total_fee: u64,
+  ) -> Option<u128> {
+    let ask_amount = self.calc_ask_amount_swap(bid_amount, bid_mint, ask_mint, total_fee)?;
+    calc_effective_price(bid_amount, ask_amount)
+  }
+
+  fn ui_spot_price(
+    &self,
+    bid_mint: Pubkey,
+    bid_decimals: u8,
+    ask_mint: Pubkey,
+    ask_decimals: u8,
+    total_fee: u64,
+  ) -> Option<Decimal> {
+    let spot_price = self.spot_price(bid_mint, ask_mint, total_fee)?;
+    fixed_price_to_ui(spot_price, bid_decimals, ask_decimals)
+  }
+
+  fn ui_effective_price(
+    &self,
+    bid_amount: u64,
+    bid_mint: Pubkey,
+    bid_decimals: u8,
+    ask_mint: Pubkey,
+    ask_decimals: u8,
+    total_fee: u64,
+  ) -> Option<Decimal> {
+    let effective_price = self.effective_price(bid_amount, bid_mint, ask_mint, total_fee)?;
+    fixed_price_to_ui(effective_price, bid_decimals, ask_decimals)
+  }
+}
+
+///
+/// Liquidity trait
+///
+impl Liquidity for Pool {
+  fn calc_starting_lpt(&self, amounts: Vec<u64>) -> Option<u64> {
+    calc_starting_lpt(amounts, self.weights.clone())
+  }
+
+  fn calc_lpt_by_full_side(&self, amounts: Vec<u64>, lpt_supply: u64) -> Option<u64> {
+    calc_lpt_by_full_side(amounts, self.reserves.clone(), lpt_supply)
+  }
+
+  fn calc_lpt_by_side(&self, amounts: Vec<u64>, lpt_supply: u64) -> Option<u64> {
+    calc_lpt_by_side(
+      amounts,
+      self.reserves.clone(),
+      self.weights.clone(),
+      lpt_supply,
+      self.fee,
+    )
+  }
+
+  fn calc_withdraw_lpt(&self, lpt: u64, lpt_supply: u64) -> Option<Vec<u64>> {
+    calc_withdraw_lpt(lpt, self.reserves.clone(), lpt_supply)
+  }
+
+  fn calc_withdraw_single(&self, lpt: u64, mint: Pubkey, lpt_supply: u64) -> Option<u64> {
+    let mint_idx = self.get_mint_index(mint)?;
+    calc_withdraw_single(
+      lpt,
+      mint_idx,
+      self.reserves.clone(),
+      self.weights.clone(),
+      lpt_supply,
+      self.fee,
+    )
+  }
+
+  fn invariant(&self) -> Option<u128> {
+    calc_invariant(self.reserves.clone(), self.weights.clone())
+  }
+
+  fn pool_value(&self, prices: Vec<Decimal>, decimals: Vec<u8>) -> Option<Decimal> {
+    calc_pool_value(self.reserves.clone(), decimals, prices)
+  }
+
+  fn lp_token_price(
+    &self,
+    prices: Vec<Decimal>,
+    decimals: Vec<u8>,
+    lpt_supply: u64,
+  ) -> Option<Decimal> {
+    calc_lp_token_price(self.pool_value(prices, decimals)?, lpt_supply)
+  }
+}
+
+#[test]
+fn test_calc_batch_swap() {
+  use crate::balansol_amm::mock_pool;
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+
+  // Rebalance USDC and USDT into SOL
+  let pool = mock_pool(
+    vec![1_000_000_000_000, 1_000_000_000_000, 100_000_000_000_000],
+    vec![1, 1, 2],
+  );
+  let (usdc, usdt, sol) = (pool.mints[0], pool.mints[1], pool.mints[2]);
+  let step = |bid_mint, ask_mint, bid_amount| BatchSwapStep {
+    bid_mint,
+    ask_mint,
+    bid_amount,
+  };
+  let after_tax =
+    |ask_amount: u64| ask_amount - (ask_amount as u128 * pool.tax as u128 / PRECISION_U128) as u64;
+
+  let batch = pool
+    .calc_batch_swap(
+      vec![
+        step(usdc, sol, 50_000_000_000),
+        step(usdt, sol, 50_000_000_000),
+      ],
+      pool.fee,
+    )
+    .unwrap();
+  // The second leg sells into a pool that already holds less SOL
+  let first_ask_amount = calc_ask_amount_swap(
+    50_000_000_000,
+    1_000_000_000_000,
+    1,
+    100_000_000_000_000,
+    2,
+    pool.fee,
+  )
+  .unwrap();
+  let second_ask_amount = calc_ask_amount_swap(
+    50_000_000_000,
+    1_000_000_000_000,
+    1,
+    100_000_000_000_000 - first_ask_amount,
+    2,
+    pool.fee,
+  )
+  .unwrap();
+  assert!(second_ask_amount < first_ask_amount);
+  assert_eq!(
+    batch.ask_amounts,
+    vec![after_tax(first_ask_amount), after_tax(second_ask_amount)]
+  );
+  assert!(batch.ask_amounts[0] < first_ask_amount);
+  assert_eq!(
+    batch.reserves,
+    vec![
+      1_050_000_000_000,
+      1_050_000_000_000,
+      100_000_000_000_000 - first_ask_amount - second_ask_amount
+    ]
+  );
+
+  assert_eq!(
+    pool.calc_batch_swap(vec![], pool.fee).unwrap().reserves,
+    pool.reserves
+  );
+  assert_eq!(pool.calc_batch_swap(vec![step(usdc, usdc, 1)], 0), None);
+  assert_eq!(
+    pool.calc_batch_swap(vec![step(usdc, Pubkey::new_unique(), 1)], 0),
+    None
+  );
+
+  // Untradable pools and mints are rejected like single swaps
+  let mut frozen_pool = pool.clone();
+  frozen_pool.state = PoolState::Frozen;
+  assert_eq!(
+    frozen_pool.calc_batch_swap(vec![step(usdc, sol, 1_000)], pool.fee),
+    None
+  );
+  let mut bid_only_pool = pool.clone();
+  bid_only_pool.actions[2] = MintActionState::BidOnly;
+  assert!(bid_only_pool
+    .calc_batch_swap(vec![step(sol, usdc, 1_000)], pool.fee)
+    .is_some());
+  assert_eq!(
+    bid_only_pool.calc_batch_swap(vec![step(usdc, sol, 1_000)], pool.fee),
+    None
+  );
+  let mut ask_only_pool = pool.clone();
+  ask_only_pool.actions[0] = MintActionState::AskOnly;
+  assert_eq!(
+    ask_only_pool.calc_batch_swap(vec![step(usdc, sol, 1_000)], pool.fee),
+    None
+  );
+
+  // Random batches against the same swaps applied one by one
+  let mut rng = StdRng::seed_from_u64(18);
+  for _ in 0..100 {
+    let steps: Vec<BatchSwapStep> = (0..rng.gen_range(1..6))
+      .map(|_| {
+        let bid_mint_idx = rng.gen_range(0..3);
+        let ask_mint_idx = (bid_mint_idx + rng.gen_range(1..3)) % 3;
+        step(
+          pool.mints[bid_mint_idx],
+          pool.mints[ask_mint_idx],
+          rng.gen_range(0..100_000_000_000),
+        )
+      })
+      .collect();
+    let batch = pool.calc_batch_swap(steps.clone(), pool.fee).unwrap();
+
+    let mut swapped_pool = pool.clone();
+    let ask_amounts: Vec<u64> = steps
+      .iter()
+      .map(|step| {
+        swapped_pool
+          .apply_swap(step.bid_mint, step.ask_mint, step.bid_amount)
+          .unwrap()
+      })
+      .collect();
+    assert_eq!(batch.ask_amounts, ask_amounts);
+    assert_eq!(batch.reserves, swapped_pool.reserves);
+  }
 }
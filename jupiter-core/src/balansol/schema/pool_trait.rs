@@ -1,5 +1,8 @@
-use crate::schema::pool::{MintActionState, PoolState};
+use crate::errors::ErrorCode;
+use crate::events::SwapEvent;
+use crate::schema::pool::{BatchSwapResult, BatchSwapStep, MintActionState, PoolState};
 use anchor_lang::prelude::*;
+use rust_decimal::Decimal;
 
 ///
 /// Pool operation trait
@@ -11,6 +14,10 @@ pub trait Operation {
   fn is_active(&self) -> bool;
   fn valid_mint_states(&self, idx: usize, mint_states: Vec<MintActionState>) -> bool;
   fn valid_pool_states(&self, pool_states: Vec<PoolState>) -> bool;
+  // True if the mint can be sold to the pool
+  fn can_bid(&self, idx: usize) -> bool;
+  // True if the mint can be bought from the pool
+  fn can_ask(&self, idx: usize) -> bool;
 }
 
 ///
@@ -24,6 +31,81 @@ pub trait Exchange {
     ask_mint: Pubkey,
     total_fee: u64,
   ) -> Option<u64>;
+  fn calc_bid_amount_swap(
+    &self,
+    ask_amount: u64,
+    bid_mint: Pubkey,
+    ask_mint: Pubkey,
+    total_fee: u64,
+  ) -> Option<u64>;
+  // Rejects swaps above `max_in_ratio` of the bid reserve or `max_out_ratio` of the ask reserve
+  fn check_swap_ratios(
+    &self,
+    bid_amount: u64,
+    bid_mint: Pubkey,
+    ask_amount: u64,
+    ask_mint: Pubkey,
+    max_in_ratio: u64,
+    max_out_ratio: u64,
+  ) -> std::result::Result<(), ErrorCode>;
+  // Applies the swaps one after another, each against the reserves left by the previous ones.
+  // None when the pool or a mint cannot trade
+  fn calc_batch_swap(&self, steps: Vec<BatchSwapStep>, total_fee: u64) -> Option<BatchSwapResult>;
+  // Swaps against the reserves like the swap instruction, returning the amount
+  // received once the taxman cut has left the ask treasury
+  fn apply_swap(&mut self, bid_mint: Pubkey, ask_mint: Pubkey, bid_amount: u64) -> Option<u64>;
+  // Marginal price of the ask mint in bid mint units, fixed-point (10^18)
+  fn spot_price(&self, bid_mint: Pubkey, ask_mint: Pubkey, total_fee: u64) -> Option<u128>;
+  // Average price paid by swapping `bid_amount`, fixed-point (10^18)
+  fn effective_price(
+    &self,
+    bid_amount: u64,
+    bid_mint: Pubkey,
+    ask_mint: Pubkey,
+    total_fee: u64,
+  ) -> Option<u128>;
+  // Spot price between UI amounts
+  fn ui_spot_price(
+    &self,
+    bid_mint: Pubkey,
+    bid_decimals: u8,
+    ask_mint: Pubkey,
+    ask_decimals: u8,
+    total_fee: u64,
+  ) -> Option<Decimal>;
+  // Effective price between UI amounts
+  fn ui_effective_price(
+    &self,
+    bid_amount: u64,
+    bid_mint: Pubkey,
+    bid_decimals: u8,
+    ask_mint: Pubkey,
+    ask_decimals: u8,
+    total_fee: u64,
+  ) -> Option<Decimal>;
+}
+
+///
+/// Pool liquidity trait, previews deposits and withdrawals and values the pool
+///
+pub trait Liquidity {
+  // LP tokens minted by the first deposit into the pool
+  fn calc_starting_lpt(&self, amounts: Vec<u64>) -> Option<u64>;
+  // LP tokens minted by a deposit proportional to the reserves
+  fn calc_lpt_by_full_side(&self, amounts: Vec<u64>, lpt_supply: u64) -> Option<u64>;
+  // LP tokens minted by a deposit of any amounts, paying the swap fee
+  fn calc_lpt_by_side(&self, amounts: Vec<u64>, lpt_supply: u64) -> Option<u64>;
+  // Amounts of every mint returned by burning LP tokens
+  fn calc_withdraw_lpt(&self, lpt: u64, lpt_supply: u64) -> Option<Vec<u64>>;
+  // Amount of a single mint returned by burning LP tokens, paying the swap fee
+  fn calc_withdraw_single(&self, lpt: u64, mint: Pubkey, lpt_supply: u64) -> Option<u64>;
+  // Weighted geometric mean of the reserves, fixed-point (10^18)
+  fn invariant(&self) -> Option<u128>;
+  // Value of the reserves given the UI price and decimals of every mint
+  fn pool_value(&self, prices: Vec<Decimal>, decimals: Vec<u8>) -> Option<Decimal>;
+  // Value of one UI LP token given the UI price and decimals of every mint
+  fn lp_token_price(&self, prices: Vec<Decimal>, decimals: Vec<u8>, lpt_supply: u64)
+    -> Option<Decimal>;
 }
 
 ///
@@ -36,4 +118,15 @@ pub trait Accessor {
   fn get_treasury_index(&self, mint: Pubkey) -> Option<usize>;
   fn get_normalized_weight(&self, mint: Pubkey) -> Option<f64>;
   fn get_reserve(&self, mint: Pubkey) -> Option<u64>;
+  // Replays the reserve changes of an executed swap
+  fn apply_swap_event(&mut self, event: &SwapEvent) -> Option<()>;
+}
+
+///
+/// Pool weight schedule trait, for pools whose weights move over time
+///
+pub trait Schedule {
+  // Raw weights at a unix timestamp
+  fn get_weights(&self, timestamp: i64) -> Option<Vec<u64>>;
+  fn get_normalized_weight(&self, mint: Pubkey, timestamp: i64) -> Option<f64>;
 }
@@ -1,9 +1,15 @@
 mod amms;
 
+pub use amms::account_provider;
 pub use amms::amm;
+pub use amms::amm_factory;
+pub use amms::error;
+pub use amms::spl_token_swap_amm;
 
 pub mod config;
 pub mod constants;
 
 pub mod balansol;
 pub use balansol::*;
+
+pub mod routes;
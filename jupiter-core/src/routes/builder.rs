@@ -0,0 +1,460 @@
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::{associated_token::get_associated_token_address, token};
+use anyhow::{anyhow, ensure, Result};
+use jupiter::jupiter_override::{Route, SplitLeg, SwapLeg};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::amm::{Amm, QuoteParams, SwapMode, SwapParams};
+
+// The Jupiter route program decodes at most Route -> SwapLegDeeper -> SwapLegSwap
+pub const MAX_ROUTE_DEPTH: usize = 3;
+
+/// How an input amount flows through a set of Amms
+pub enum RoutePlan<'a> {
+    Swap {
+        amm: &'a dyn Amm,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+    },
+    // Each leg swaps the whole output of the previous one
+    Chain(Vec<RoutePlan<'a>>),
+    // Each leg swaps `percent` of the input, the percents add up to 100
+    Split(Vec<(u8, RoutePlan<'a>)>),
+}
+
+impl<'a> RoutePlan<'a> {
+    pub fn input_mint(&self) -> Option<Pubkey> {
+        match self {
+            RoutePlan::Swap { input_mint, .. } => Some(*input_mint),
+            RoutePlan::Chain(legs) => legs.first()?.input_mint(),
+            RoutePlan::Split(legs) => legs.first()?.1.input_mint(),
+        }
+    }
+
+    pub fn output_mint(&self) -> Option<Pubkey> {
+        match self {
+            RoutePlan::Swap { output_mint, .. } => Some(*output_mint),
+            RoutePlan::Chain(legs) => legs.last()?.output_mint(),
+            RoutePlan::Split(legs) => legs.first()?.1.output_mint(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        match self {
+            RoutePlan::Swap { .. } => 1,
+            RoutePlan::Chain(legs) => 1 + legs.iter().map(|leg| leg.depth()).max().unwrap_or(0),
+            RoutePlan::Split(legs) => {
+                1 + legs.iter().map(|(_, leg)| leg.depth()).max().unwrap_or(0)
+            }
+        }
+    }
+
+    /// Checks that consecutive legs connect and that splits are complete
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            RoutePlan::Swap {
+                input_mint,
+                output_mint,
+                ..
+            } => ensure!(input_mint != output_mint, "Swap leg has the same mints"),
+            RoutePlan::Chain(legs) => {
+                ensure!(!legs.is_empty(), "Chain leg is empty");
+                for leg in legs {
+                    leg.validate()?;
+                }
+                for pair in legs.windows(2) {
+                    ensure!(
+                        pair[0].output_mint() == pair[1].input_mint(),
+                        "Chain legs are not connected"
+                    );
+                }
+            }
+            RoutePlan::Split(legs) => {
+                ensure!(!legs.is_empty(), "Split leg is empty");
+                for (_, leg) in legs {
+                    leg.validate()?;
+                    ensure!(
+                        leg.input_mint() == self.input_mint()
+                            && leg.output_mint() == self.output_mint(),
+                        "Split legs do not share their mints"
+                    );
+                }
+                let total_percent: u32 = legs.iter().map(|(percent, _)| *percent as u32).sum();
+                ensure!(
+                    total_percent == 100,
+                    "Split percents add up to {}",
+                    total_percent
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Output amount of the plan for `in_amount`, quoting every leg. Fails
+    /// when a leg does not have enough liquidity for its share
+    pub fn quote(&self, in_amount: u64) -> Result<u64> {
+        match self {
+            RoutePlan::Swap {
+                amm,
+                input_mint,
+                output_mint,
+            } => {
+                let quote = amm.quote(&QuoteParams {
+                    amount: in_amount,
+                    input_mint: *input_mint,
+                    output_mint: *output_mint,
+                    swap_mode: SwapMode::ExactIn,
+                })?;
+                ensure!(
+                    !quote.not_enough_liquidity,
+                    "{} pool {} does not have enough liquidity to swap {}",
+                    amm.label(),
+                    amm.key(),
+                    in_amount
+                );
+                Ok(quote.out_amount)
+            }
+            RoutePlan::Chain(legs) => legs
+                .iter()
+                .try_fold(in_amount, |amount, leg| leg.quote(amount)),
+            RoutePlan::Split(legs) => {
+                let mut out_amount = 0_u64;
+                for ((_, leg), leg_in_amount) in legs.iter().zip(split_amounts(legs, in_amount)) {
+                    out_amount = out_amount
+                        .checked_add(leg.quote(leg_in_amount)?)
+                        .ok_or_else(|| anyhow!("Split output overflows"))?;
+                }
+                Ok(out_amount)
+            }
+        }
+    }
+}
+
+// Splits `in_amount` by percent, the rounding remainder goes to the last leg
+fn split_amounts(legs: &[(u8, RoutePlan)], in_amount: u64) -> Vec<u64> {
+    let mut amounts: Vec<u64> = legs
+        .iter()
+        .map(|(percent, _)| (in_amount as u128 * *percent as u128 / 100) as u64)
+        .collect();
+    let remainder = in_amount - amounts.iter().sum::<u64>();
+    if let Some(last) = amounts.last_mut() {
+        *last += remainder;
+    }
+    amounts
+}
+
+/// Builds Jupiter `route` instructions from route plans.
+///
+/// Intermediate and final tokens go through the associated token accounts of
+/// `user_transfer_authority`.
+pub struct RouteBuilder {
+    pub user_transfer_authority: Pubkey,
+    pub slippage_bps: u16,
+    pub platform_fee_bps: u8,
+    // Defaults to the associated token account of the output mint
+    pub destination_token_account: Option<Pubkey>,
+}
+
+impl RouteBuilder {
+    pub fn new(user_transfer_authority: Pubkey, slippage_bps: u16, platform_fee_bps: u8) -> Self {
+        Self {
+            user_transfer_authority,
+            slippage_bps,
+            platform_fee_bps,
+            destination_token_account: None,
+        }
+    }
+
+    pub fn build(&self, plan: &RoutePlan, in_amount: u64) -> Result<Instruction> {
+        plan.validate()?;
+        ensure!(
+            plan.depth() <= MAX_ROUTE_DEPTH,
+            "Route is nested deeper than {} legs",
+            MAX_ROUTE_DEPTH
+        );
+        let output_mint = plan
+            .output_mint()
+            .ok_or_else(|| anyhow!("Route has no output mint"))?;
+
+        let mut leg_account_metas = vec![];
+        let (swap_leg, quoted_out_amount) =
+            self.build_leg(plan, in_amount, &mut leg_account_metas)?;
+
+        let mut accounts = jupiter::accounts::Route {
+            token_program: token::ID,
+            user_transfer_authority: self.user_transfer_authority,
+            destination_token_account: self.destination_token_account.unwrap_or_else(|| {
+                get_associated_token_address(&self.user_transfer_authority, &output_mint)
+            }),
+        }
+        .to_account_metas(None);
+        accounts.append(&mut leg_account_metas);
+
+        let data = Route {
+            swap_leg,
+            in_amount,
+            quoted_out_amount,
+            slippage_bps: self.slippage_bps,
+            platform_fee_bps: self.platform_fee_bps,
+        }
+        .data();
+
+        Ok(Instruction {
+            program_id: jupiter::ID,
+            accounts,
+            data,
+        })
+    }
+
+    fn build_leg(
+        &self,
+        plan: &RoutePlan,
+        in_amount: u64,
+        account_metas: &mut Vec<AccountMeta>,
+    ) -> Result<(SwapLeg, u64)> {
+        match plan {
+            RoutePlan::Swap {
+                amm,
+                input_mint,
+                output_mint,
+            } => {
+                let out_amount = plan.quote(in_amount)?;
+                let mut swap_leg_and_account_metas =
+                    amm.get_swap_leg_and_account_metas(&SwapParams {
+                        source_mint: *input_mint,
+                        destination_mint: *output_mint,
+                        user_source_token_account: get_associated_token_address(
+                            &self.user_transfer_authority,
+                            input_mint,
+                        ),
+                        user_destination_token_account: get_associated_token_address(
+                            &self.user_transfer_authority,
+                            output_mint,
+                        ),
+                        user_transfer_authority: self.user_transfer_authority,
+                        open_order_address: None,
+                        quote_mint_to_referrer: None,
+                        in_amount,
+                    })?;
+                account_metas.append(&mut swap_leg_and_account_metas.account_metas);
+                Ok((swap_leg_and_account_metas.swap_leg, out_amount))
+            }
+            RoutePlan::Chain(legs) => {
+                let mut swap_legs = vec![];
+                let mut amount = in_amount;
+                for leg in legs {
+                    let (swap_leg, out_amount) = self.build_leg(leg, amount, account_metas)?;
+                    swap_legs.push(swap_leg);
+                    amount = out_amount;
+                }
+                Ok((SwapLeg::Chain { swap_legs }, amount))
+            }
+            RoutePlan::Split(legs) => {
+                let mut split_legs = vec![];
+                let mut out_amount = 0_u64;
+                for ((percent, leg), leg_in_amount) in
+                    legs.iter().zip(split_amounts(legs, in_amount))
+                {
+                    let (swap_leg, leg_out_amount) =
+                        self.build_leg(leg, leg_in_amount, account_metas)?;
+                    split_legs.push(SplitLeg {
+                        percent: *percent,
+                        swap_leg,
+                    });
+                    out_amount = out_amount
+                        .checked_add(leg_out_amount)
+                        .ok_or_else(|| anyhow!("Split output overflows"))?;
+                }
+                Ok((SwapLeg::Split { split_legs }, out_amount))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_build_single_swap_route() {
+    use crate::balansol_amm::{mock_keyed_account, mock_pool, BalansolAmm};
+
+    let pool = mock_pool(
+        vec![10_000_000_000, 20_000_000_000],
+        vec![500_000_000, 500_000_000],
+    );
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let plan = RoutePlan::Swap {
+        amm: &amm,
+        input_mint: pool.mints[0],
+        output_mint: pool.mints[1],
+    };
+    let user = Pubkey::new_unique();
+    let builder = RouteBuilder::new(user, 50, 0);
+    let instruction = builder.build(&plan, 1_000_000).unwrap();
+
+    assert_eq!(instruction.program_id, jupiter::ID);
+    let quoted_out_amount = plan.quote(1_000_000).unwrap();
+    let route = Route::try_from_instruction_data(&instruction.data).unwrap();
+    assert_eq!(route.swap_leg.to_string(), "Balansol\n");
+    assert_eq!(route.in_amount, 1_000_000);
+    assert_eq!(route.quoted_out_amount, quoted_out_amount);
+    assert_eq!(route.slippage_bps, 50);
+    assert_eq!(route.platform_fee_bps, 0);
+
+    // Route accounts, then the 16 Balansol swap accounts
+    assert_eq!(instruction.accounts.len(), 3 + 16);
+    assert_eq!(instruction.accounts[0].pubkey, token::ID);
+    assert_eq!(instruction.accounts[1].pubkey, user);
+    assert!(instruction.accounts[1].is_signer);
+    assert_eq!(
+        instruction.accounts[2].pubkey,
+        get_associated_token_address(&user, &pool.mints[1])
+    );
+}
+
+#[test]
+fn test_build_chain_and_split_route() {
+    use crate::balansol_amm::{mock_keyed_account, mock_pool, BalansolAmm};
+
+    let first_pool = mock_pool(
+        vec![10_000_000_000, 20_000_000_000],
+        vec![500_000_000, 500_000_000],
+    );
+    let mut second_pool = mock_pool(
+        vec![30_000_000_000, 5_000_000_000],
+        vec![800_000_000, 200_000_000],
+    );
+    let mut third_pool = mock_pool(
+        vec![40_000_000_000, 6_000_000_000],
+        vec![500_000_000, 500_000_000],
+    );
+    // first: A -> B, second and third: B -> C
+    second_pool.mints[0] = first_pool.mints[1];
+    third_pool.mints[0] = first_pool.mints[1];
+    third_pool.mints[1] = second_pool.mints[1];
+    let first_amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&first_pool)).unwrap();
+    let second_amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&second_pool)).unwrap();
+    let third_amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&third_pool)).unwrap();
+
+    let (mint_a, mint_b, mint_c) = (
+        first_pool.mints[0],
+        first_pool.mints[1],
+        second_pool.mints[1],
+    );
+    let plan = RoutePlan::Chain(vec![
+        RoutePlan::Swap {
+            amm: &first_amm,
+            input_mint: mint_a,
+            output_mint: mint_b,
+        },
+        RoutePlan::Split(vec![
+            (
+                70,
+                RoutePlan::Swap {
+                    amm: &second_amm,
+                    input_mint: mint_b,
+                    output_mint: mint_c,
+                },
+            ),
+            (
+                30,
+                RoutePlan::Swap {
+                    amm: &third_amm,
+                    input_mint: mint_b,
+                    output_mint: mint_c,
+                },
+            ),
+        ]),
+    ]);
+    let instruction = RouteBuilder::new(Pubkey::new_unique(), 100, 20)
+        .build(&plan, 1_000_000_000)
+        .unwrap();
+
+    let route = Route::try_from_instruction_data(&instruction.data).unwrap();
+    assert_eq!(
+        route.swap_leg.to_string(),
+        "Chain
+  Balansol
+  Split
+    70%
+      Balansol
+    30%
+      Balansol
+"
+    );
+    assert_eq!(route.quoted_out_amount, plan.quote(1_000_000_000).unwrap());
+    assert_eq!(route.platform_fee_bps, 20);
+    assert_eq!(instruction.accounts.len(), 3 + 3 * 16);
+}
+
+#[test]
+fn test_build_rejects_invalid_plans() {
+    use crate::balansol_amm::{mock_keyed_account, mock_pool, BalansolAmm};
+
+    let pool = mock_pool(
+        vec![10_000_000_000, 20_000_000_000],
+        vec![500_000_000, 500_000_000],
+    );
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let swap = || RoutePlan::Swap {
+        amm: &amm,
+        input_mint: pool.mints[0],
+        output_mint: pool.mints[1],
+    };
+    let builder = RouteBuilder::new(Pubkey::new_unique(), 50, 0);
+
+    // Percents must add up to 100
+    let plan = RoutePlan::Split(vec![(60, swap()), (30, swap())]);
+    assert!(builder.build(&plan, 1_000).is_err());
+    // A -> B then A -> B is not connected
+    let plan = RoutePlan::Chain(vec![swap(), swap()]);
+    assert!(builder.build(&plan, 1_000).is_err());
+    // Nested deeper than the program decodes
+    let plan = RoutePlan::Chain(vec![RoutePlan::Split(vec![(
+        100,
+        RoutePlan::Chain(vec![swap()]),
+    )])]);
+    assert!(builder.build(&plan, 1_000).is_err());
+}
+
+#[test]
+fn test_build_rejects_illiquid_legs() {
+    use crate::balansol_amm::{mock_keyed_account, mock_pool, BalansolAmm};
+
+    let first_pool = mock_pool(
+        vec![10_000_000_000, 20_000_000_000],
+        vec![500_000_000, 500_000_000],
+    );
+    let mut second_pool = mock_pool(
+        vec![3_000_000_000, 5_000_000_000],
+        vec![500_000_000, 500_000_000],
+    );
+    second_pool.mints[0] = first_pool.mints[1];
+    let first_amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&first_pool)).unwrap();
+    let second_amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&second_pool)).unwrap();
+    let first_swap = || RoutePlan::Swap {
+        amm: &first_amm,
+        input_mint: first_pool.mints[0],
+        output_mint: first_pool.mints[1],
+    };
+    let builder = RouteBuilder::new(Pubkey::new_unique(), 50, 0);
+    assert!(builder.build(&first_swap(), 1_000_000_000).is_ok());
+
+    // Bids more than 30% of the bid reserve
+    let err = builder.build(&first_swap(), 5_000_000_000).unwrap_err();
+    assert!(err.to_string().contains("does not have enough liquidity"));
+    assert!(first_swap().quote(5_000_000_000).is_err());
+
+    // The first leg is fine, the second one gets more than it can take
+    let plan = RoutePlan::Chain(vec![
+        first_swap(),
+        RoutePlan::Swap {
+            amm: &second_amm,
+            input_mint: second_pool.mints[0],
+            output_mint: second_pool.mints[1],
+        },
+    ]);
+    assert!(builder.build(&plan, 100_000_000).is_ok());
+    let err = builder.build(&plan, 1_000_000_000).unwrap_err();
+    assert!(err.to_string().contains(&second_amm.key().to_string()));
+}
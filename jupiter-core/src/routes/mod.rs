@@ -0,0 +1,7 @@
+pub mod builder;
+pub mod router;
+pub mod split;
+
+pub use builder::*;
+pub use router::*;
+pub use split::*;
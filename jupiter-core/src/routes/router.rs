@@ -0,0 +1,344 @@
+use anyhow::{anyhow, ensure, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+use super::builder::RoutePlan;
+use crate::amm::{Amm, Quote, QuoteParams, SwapMode};
+
+// Longest chain the router searches, each hop being one Amm swap
+pub const MAX_HOPS: usize = 3;
+
+/// One hop of a route and its quote
+#[derive(Clone)]
+pub struct RouteLeg<'a> {
+    pub amm: &'a dyn Amm,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub quote: Quote,
+}
+
+/// A path through the token graph, quoted for `in_amount`
+#[derive(Clone)]
+pub struct QuotedRoute<'a> {
+    pub legs: Vec<RouteLeg<'a>>,
+}
+
+impl<'a> QuotedRoute<'a> {
+    pub fn in_amount(&self) -> u64 {
+        self.legs.first().map_or(0, |leg| leg.quote.in_amount)
+    }
+
+    pub fn out_amount(&self) -> u64 {
+        self.legs.last().map_or(0, |leg| leg.quote.out_amount)
+    }
+
+    /// Input mint, intermediate mints, then output mint
+    pub fn mints(&self) -> Vec<Pubkey> {
+        let mut mints: Vec<Pubkey> = self.legs.iter().map(|leg| leg.input_mint).collect();
+        mints.extend(self.legs.last().map(|leg| leg.output_mint));
+        mints
+    }
+
+    /// The route as a plan for `RouteBuilder`, a single swap or a chain of swaps
+    pub fn plan(&self) -> RoutePlan<'a> {
+        let mut swaps: Vec<RoutePlan<'a>> = self
+            .legs
+            .iter()
+            .map(|leg| RoutePlan::Swap {
+                amm: leg.amm,
+                input_mint: leg.input_mint,
+                output_mint: leg.output_mint,
+            })
+            .collect();
+        if swaps.len() == 1 {
+            swaps.remove(0)
+        } else {
+            RoutePlan::Chain(swaps)
+        }
+    }
+}
+
+/// Finds routes between two mints through a set of Amms, quoting every
+/// candidate path hop by hop.
+pub struct Router<'a> {
+    amms: Vec<&'a dyn Amm>,
+    // Input mint to the (amm index, output mint) swaps it can go through
+    graph: HashMap<Pubkey, Vec<(usize, Pubkey)>>,
+}
+
+impl<'a> Router<'a> {
+    pub fn new(amms: Vec<&'a dyn Amm>) -> Self {
+        let mut graph: HashMap<Pubkey, Vec<(usize, Pubkey)>> = HashMap::new();
+        for (amm_index, amm) in amms.iter().enumerate() {
+            let reserve_mints = amm.get_reserve_mints_with_direction();
+            for input in reserve_mints.iter().filter(|reserve| reserve.input) {
+                for output in reserve_mints.iter().filter(|reserve| reserve.output) {
+                    if input.mint != output.mint {
+                        graph
+                            .entry(input.mint)
+                            .or_default()
+                            .push((amm_index, output.mint));
+                    }
+                }
+            }
+        }
+        Self { amms, graph }
+    }
+
+    /// The best route of each length from 1 to `max_hops`, shortest first.
+    /// Lengths without any liquid path are left out.
+    pub fn find_routes(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        in_amount: u64,
+        max_hops: usize,
+    ) -> Result<Vec<QuotedRoute<'a>>> {
+        ensure!(
+            (1..=MAX_HOPS).contains(&max_hops),
+            "Routes have 1 to {} hops",
+            MAX_HOPS
+        );
+        ensure!(input_mint != output_mint, "Route has the same mints");
+        let mut best_routes: Vec<Option<QuotedRoute<'a>>> = vec![None; max_hops];
+        let mut legs = vec![];
+        self.search(
+            input_mint,
+            output_mint,
+            in_amount,
+            max_hops,
+            &mut legs,
+            &mut best_routes,
+        );
+        Ok(best_routes.into_iter().flatten().collect())
+    }
+
+    /// The route with the largest output, the shortest one on ties
+    pub fn find_best_route(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        in_amount: u64,
+        max_hops: usize,
+    ) -> Result<QuotedRoute<'a>> {
+        self.find_routes(input_mint, output_mint, in_amount, max_hops)?
+            .into_iter()
+            .fold(None, |best: Option<QuotedRoute<'a>>, route| match best {
+                Some(best) if best.out_amount() >= route.out_amount() => Some(best),
+                _ => Some(route),
+            })
+            .ok_or_else(|| anyhow!("No route from {} to {}", input_mint, output_mint))
+    }
+
+    // Depth first over simple paths, neither a mint nor an Amm is visited twice
+    // since the quotes of a pool do not see the earlier swaps of the route
+    fn search(
+        &self,
+        mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        max_hops: usize,
+        legs: &mut Vec<RouteLeg<'a>>,
+        best_routes: &mut Vec<Option<QuotedRoute<'a>>>,
+    ) {
+        if legs.len() == max_hops {
+            return;
+        }
+        let edges = match self.graph.get(&mint) {
+            Some(edges) => edges,
+            None => return,
+        };
+        for (amm_index, next_mint) in edges {
+            let amm = self.amms[*amm_index];
+            let visited = legs
+                .iter()
+                .any(|leg| leg.input_mint == *next_mint || leg.amm.key() == amm.key());
+            if visited || *next_mint == mint {
+                continue;
+            }
+            let quote = match amm.quote(&QuoteParams {
+                amount,
+                input_mint: mint,
+                output_mint: *next_mint,
+                swap_mode: SwapMode::ExactIn,
+            }) {
+                Ok(quote) if !quote.not_enough_liquidity && quote.out_amount > 0 => quote,
+                _ => continue,
+            };
+            legs.push(RouteLeg {
+                amm,
+                input_mint: mint,
+                output_mint: *next_mint,
+                quote,
+            });
+            if *next_mint == output_mint {
+                let best_route = &mut best_routes[legs.len() - 1];
+                if best_route
+                    .as_ref()
+                    .is_none_or(|best| quote.out_amount > best.out_amount())
+                {
+                    *best_route = Some(QuotedRoute { legs: legs.clone() });
+                }
+            } else {
+                self.search(
+                    *next_mint,
+                    output_mint,
+                    quote.out_amount,
+                    max_hops,
+                    legs,
+                    best_routes,
+                );
+            }
+            legs.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+fn mock_amm(mints: [Pubkey; 2], reserves: [u64; 2]) -> crate::balansol_amm::BalansolAmm {
+    use crate::balansol_amm::{mock_keyed_account, mock_pool, BalansolAmm};
+
+    let mut pool = mock_pool(reserves.to_vec(), vec![500_000_000, 500_000_000]);
+    pool.mints = mints.to_vec();
+    BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap()
+}
+
+#[test]
+fn test_find_routes() {
+    use super::builder::RouteBuilder;
+    use jupiter::jupiter_override::Route;
+
+    let [mint_a, mint_b, mint_c, mint_d] = [0; 4].map(|_| Pubkey::new_unique());
+    let deep = 1_000_000_000_000;
+    let amm_ab = mock_amm([mint_a, mint_b], [deep, deep]);
+    let amm_bc = mock_amm([mint_b, mint_c], [deep, deep]);
+    let amm_ac = mock_amm([mint_a, mint_c], [1_000_000_000, 1_000_000_000]);
+    let amm_cd = mock_amm([mint_c, mint_d], [deep, 2 * deep]);
+    let router = Router::new(vec![&amm_ab, &amm_bc, &amm_ac, &amm_cd]);
+
+    // The shallow direct pool wins small swaps, the deep detour large ones
+    let route = router
+        .find_best_route(mint_a, mint_c, 1_000_000, MAX_HOPS)
+        .unwrap();
+    assert_eq!(route.mints(), vec![mint_a, mint_c]);
+    let route = router
+        .find_best_route(mint_a, mint_c, 100_000_000, MAX_HOPS)
+        .unwrap();
+    assert_eq!(route.mints(), vec![mint_a, mint_b, mint_c]);
+    assert_eq!(route.legs[1].amm.key(), amm_bc.key());
+
+    let routes = router
+        .find_routes(mint_a, mint_d, 100_000_000, MAX_HOPS)
+        .unwrap();
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes[0].mints(), vec![mint_a, mint_c, mint_d]);
+    assert_eq!(routes[1].mints(), vec![mint_a, mint_b, mint_c, mint_d]);
+    assert!(routes[1].out_amount() > routes[0].out_amount());
+    // Each leg swaps the whole output of the previous one
+    let best = router
+        .find_best_route(mint_a, mint_d, 100_000_000, MAX_HOPS)
+        .unwrap();
+    assert_eq!(best.mints(), routes[1].mints());
+    assert_eq!(best.in_amount(), 100_000_000);
+    for pair in best.legs.windows(2) {
+        assert_eq!(pair[0].quote.out_amount, pair[1].quote.in_amount);
+    }
+    assert_eq!(best.plan().quote(100_000_000).unwrap(), best.out_amount());
+
+    let instruction = RouteBuilder::new(Pubkey::new_unique(), 50, 0)
+        .build(&best.plan(), 100_000_000)
+        .unwrap();
+    let route = Route::try_from_instruction_data(&instruction.data).unwrap();
+    assert_eq!(
+        route.swap_leg.to_string(),
+        "Chain\n  Balansol\n  Balansol\n  Balansol\n"
+    );
+    assert_eq!(route.quoted_out_amount, best.out_amount());
+    assert_eq!(instruction.accounts.len(), 3 + 3 * 16);
+
+    // A single hop route builds a plain swap leg
+    let route = router.find_best_route(mint_a, mint_b, 1_000, 1).unwrap();
+    let instruction = RouteBuilder::new(Pubkey::new_unique(), 50, 0)
+        .build(&route.plan(), 1_000)
+        .unwrap();
+    let route = Route::try_from_instruction_data(&instruction.data).unwrap();
+    assert_eq!(route.swap_leg.to_string(), "Balansol\n");
+}
+
+#[test]
+fn test_find_routes_errors() {
+    let [mint_a, mint_b, mint_c, mint_d] = [0; 4].map(|_| Pubkey::new_unique());
+    let deep = 1_000_000_000_000;
+    let amm_ab = mock_amm([mint_a, mint_b], [deep, deep]);
+    let amm_bc = mock_amm([mint_b, mint_c], [deep, deep]);
+    let tiny_cd = mock_amm([mint_c, mint_d], [1_000, 1_000]);
+    let router = Router::new(vec![&amm_ab, &amm_bc, &tiny_cd]);
+
+    assert!(router.find_best_route(mint_a, mint_c, 1_000, 1).is_err());
+    assert!(router.find_best_route(mint_a, mint_c, 1_000, 2).is_ok());
+    assert!(router.find_routes(mint_a, mint_c, 1_000, 0).is_err());
+    assert!(router
+        .find_routes(mint_a, mint_c, 1_000, MAX_HOPS + 1)
+        .is_err());
+    assert!(router.find_routes(mint_a, mint_a, 1_000, MAX_HOPS).is_err());
+    assert!(router
+        .find_best_route(mint_a, Pubkey::new_unique(), 1_000, MAX_HOPS)
+        .is_err());
+    // Paths through a pool without enough liquidity are dropped
+    let err = router
+        .find_best_route(mint_a, mint_d, 1_000_000_000, MAX_HOPS)
+        .err()
+        .unwrap();
+    assert!(err.to_string().starts_with("No route from"));
+    assert!(router
+        .find_best_route(mint_a, mint_d, 100, MAX_HOPS)
+        .is_ok());
+}
+
+#[test]
+fn test_lp_mint_is_not_routed() {
+    use crate::balansol_amm::{mock_keyed_account, mock_lp_amm, mock_pool, BalansolAmm};
+
+    let deep = 1_000_000_000_000;
+    let lp_pool = mock_pool(vec![deep, deep], vec![1, 1]);
+    let lp_amm = mock_lp_amm(&lp_pool, deep);
+    // A pool trading the LP token against another mint
+    let mut lpt_pool = mock_pool(vec![deep, deep], vec![1, 1]);
+    lpt_pool.mints[0] = lp_pool.mint_lpt;
+    let lpt_amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&lpt_pool)).unwrap();
+    let router = Router::new(vec![&lp_amm, &lpt_amm]);
+    let (mint_a, mint_b, mint_lpt, mint_c) = (
+        lp_pool.mints[0],
+        lp_pool.mints[1],
+        lp_pool.mint_lpt,
+        lpt_pool.mints[1],
+    );
+
+    // The LP Amm quotes deposits, but no route deposits or withdraws
+    assert!(lp_amm
+        .quote(&QuoteParams {
+            amount: 1_000_000,
+            input_mint: mint_a,
+            output_mint: mint_lpt,
+            swap_mode: SwapMode::ExactIn,
+        })
+        .is_ok());
+    assert!(router
+        .find_best_route(mint_a, mint_b, 1_000_000, MAX_HOPS)
+        .is_ok());
+    assert!(router
+        .find_best_route(mint_a, mint_lpt, 1_000_000, MAX_HOPS)
+        .is_err());
+    assert!(router
+        .find_best_route(mint_lpt, mint_a, 1_000_000, MAX_HOPS)
+        .is_err());
+    assert!(router
+        .find_best_route(mint_a, mint_c, 1_000_000, MAX_HOPS)
+        .is_err());
+    // The LP token still trades in pools holding it
+    let route = router
+        .find_best_route(mint_c, mint_lpt, 1_000_000, MAX_HOPS)
+        .unwrap();
+    assert_eq!(route.legs.len(), 1);
+    assert_eq!(route.legs[0].amm.key(), lpt_amm.key());
+}
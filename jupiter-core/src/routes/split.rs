@@ -0,0 +1,279 @@
+use anyhow::{anyhow, ensure, Result};
+use solana_sdk::pubkey::Pubkey;
+
+use super::builder::RoutePlan;
+use crate::amm::{Amm, Quote, QuoteParams, SwapMode};
+
+/// The share of a split going through one Amm, and its quote
+#[derive(Clone)]
+pub struct SplitRouteLeg<'a> {
+    pub percent: u8,
+    pub amm: &'a dyn Amm,
+    pub quote: Quote,
+}
+
+/// An input amount spread across Amms quoting the same pair
+#[derive(Clone)]
+pub struct QuotedSplit<'a> {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub legs: Vec<SplitRouteLeg<'a>>,
+}
+
+impl<'a> QuotedSplit<'a> {
+    pub fn in_amount(&self) -> u64 {
+        self.legs.iter().map(|leg| leg.quote.in_amount).sum()
+    }
+
+    pub fn out_amount(&self) -> u64 {
+        self.legs.iter().map(|leg| leg.quote.out_amount).sum()
+    }
+
+    /// The split as a plan for `RouteBuilder`
+    pub fn plan(&self) -> RoutePlan<'a> {
+        RoutePlan::Split(
+            self.legs
+                .iter()
+                .map(|leg| {
+                    (
+                        leg.percent,
+                        RoutePlan::Swap {
+                            amm: leg.amm,
+                            input_mint: self.input_mint,
+                            output_mint: self.output_mint,
+                        },
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+// Quotes that cannot be routed, failed or without enough liquidity, are None
+fn quote_exact_in(
+    amm: &dyn Amm,
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount: u64,
+) -> Option<Quote> {
+    if amount == 0 {
+        return None;
+    }
+    amm.quote(&QuoteParams {
+        amount,
+        input_mint,
+        output_mint,
+        swap_mode: SwapMode::ExactIn,
+    })
+    .ok()
+    .filter(|quote| !quote.not_enough_liquidity)
+}
+
+/// Spreads `in_amount` across `amms` in whole percents, maximizing the total
+/// output amount. Amms left at 0% are not part of the split.
+///
+/// Every Amm is quoted at each percent, then the best allocation is solved
+/// exactly over those quotes, so the output curves need not be concave.
+pub fn optimize_split<'a>(
+    amms: &[&'a dyn Amm],
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    in_amount: u64,
+) -> Result<QuotedSplit<'a>> {
+    ensure!(!amms.is_empty(), "Split has no Amm");
+    ensure!(input_mint != output_mint, "Split has the same mints");
+
+    // out_amounts[i][percent], None when the Amm cannot take that share
+    let out_amounts: Vec<Vec<Option<u64>>> = amms
+        .iter()
+        .map(|amm| {
+            (0..=100_u64)
+                .map(|percent| match percent {
+                    0 => Some(0),
+                    _ => quote_exact_in(
+                        *amm,
+                        input_mint,
+                        output_mint,
+                        (in_amount as u128 * percent as u128 / 100) as u64,
+                    )
+                    .map(|quote| quote.out_amount),
+                })
+                .collect()
+        })
+        .collect();
+
+    // best[i][total] is the largest output of the first i Amms sharing `total`
+    // percents, along with the percent given to Amm i - 1
+    let mut best: Vec<Vec<Option<(u128, usize)>>> = vec![vec![None; 101]; amms.len() + 1];
+    best[0][0] = Some((0, 0));
+    for (i, amm_out_amounts) in out_amounts.iter().enumerate() {
+        for total in 0..=100 {
+            for percent in 0..=total {
+                let candidate = match (best[i][total - percent], amm_out_amounts[percent]) {
+                    (Some((out_amount, _)), Some(amm_out_amount)) => {
+                        out_amount + amm_out_amount as u128
+                    }
+                    _ => continue,
+                };
+                if best[i + 1][total].is_none_or(|(out_amount, _)| candidate > out_amount) {
+                    best[i + 1][total] = Some((candidate, percent));
+                }
+            }
+        }
+    }
+    ensure!(
+        best[amms.len()][100].is_some_and(|(out_amount, _)| out_amount > 0),
+        "No split from {} to {} for {}",
+        input_mint,
+        output_mint,
+        in_amount
+    );
+
+    let mut percents = vec![0; amms.len()];
+    let mut total = 100;
+    for i in (0..amms.len()).rev() {
+        let (_, percent) = best[i + 1][total].unwrap();
+        percents[i] = percent;
+        total -= percent;
+    }
+
+    // Quote the legs as `RoutePlan` splits them, the rounding remainder going
+    // to the last leg
+    let chosen: Vec<(u8, &'a dyn Amm)> = amms
+        .iter()
+        .zip(percents)
+        .filter(|(_, percent)| *percent > 0)
+        .map(|(amm, percent)| (percent as u8, *amm))
+        .collect();
+    let mut remaining = in_amount;
+    let mut legs = vec![];
+    for (index, (percent, amm)) in chosen.iter().enumerate() {
+        let amount = if index + 1 == chosen.len() {
+            remaining
+        } else {
+            (in_amount as u128 * *percent as u128 / 100) as u64
+        };
+        remaining -= amount;
+        let quote = quote_exact_in(*amm, input_mint, output_mint, amount)
+            .ok_or_else(|| anyhow!("Split leg through {} cannot be quoted", amm.key()))?;
+        legs.push(SplitRouteLeg {
+            percent: *percent,
+            amm: *amm,
+            quote,
+        });
+    }
+    Ok(QuotedSplit {
+        input_mint,
+        output_mint,
+        legs,
+    })
+}
+
+#[test]
+fn test_optimize_split() {
+    use super::builder::RouteBuilder;
+    use crate::amms::spl_token_swap_amm::{mock_spl_token_swap, SplTokenSwapAmm};
+    use crate::balansol_amm::{mock_keyed_account, mock_pool, BalansolAmm};
+    use jupiter::jupiter_override::Route;
+    use spl_token_swap::curve::{base::SwapCurve, constant_product::ConstantProductCurve};
+    use std::sync::Arc;
+
+    let (keyed_account, swap_v1, accounts_map) = mock_spl_token_swap(
+        spl_token_swap::id(),
+        SwapCurve {
+            curve_type: spl_token_swap::curve::base::CurveType::ConstantProduct,
+            calculator: Arc::new(ConstantProductCurve {}),
+        },
+        [20_000_000_000, 20_000_000_000],
+    );
+    let mut token_swap_amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+    token_swap_amm.update(&accounts_map).unwrap();
+    let (mint_a, mint_b) = (swap_v1.token_a_mint, swap_v1.token_b_mint);
+    let balansol_amm = |reserves: Vec<u64>| {
+        let mut pool = mock_pool(reserves, vec![500_000_000, 500_000_000]);
+        pool.mints = vec![mint_a, mint_b];
+        BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap()
+    };
+    let deep_amm = balansol_amm(vec![50_000_000_000, 50_000_000_000]);
+    let shallow_amm = balansol_amm(vec![10_000_000_000, 10_000_000_000]);
+    let amms: Vec<&dyn Amm> = vec![&deep_amm, &shallow_amm, &token_swap_amm];
+
+    let in_amount = 5_000_000_000;
+    let split = optimize_split(&amms, mint_a, mint_b, in_amount).unwrap();
+    assert_eq!(split.legs.len(), 3);
+    assert_eq!(
+        split.legs.iter().map(|leg| leg.percent as u32).sum::<u32>(),
+        100
+    );
+    assert_eq!(split.in_amount(), in_amount);
+    // The deepest pool takes the largest share
+    assert!(split.legs[0].percent > split.legs[2].percent);
+    assert!(split.legs[2].percent > split.legs[1].percent);
+    assert_eq!(split.plan().quote(in_amount).unwrap(), split.out_amount());
+
+    // Beats every single pool and every coarser split
+    let split_out_amount = |percents: [u8; 3]| {
+        let legs: Vec<(u8, RoutePlan)> = percents
+            .iter()
+            .zip(&amms)
+            .filter(|(percent, _)| **percent > 0)
+            .map(|(percent, amm)| {
+                (
+                    *percent,
+                    RoutePlan::Swap {
+                        amm: *amm,
+                        input_mint: mint_a,
+                        output_mint: mint_b,
+                    },
+                )
+            })
+            .collect();
+        RoutePlan::Split(legs).quote(in_amount)
+    };
+    for first in (0..=100).step_by(10) {
+        for second in (0..=100 - first).step_by(10) {
+            let percents = [first, second, 100 - first - second];
+            // Splits sending a pool more than it can take are not routable
+            if let Ok(out_amount) = split_out_amount(percents) {
+                assert!(split.out_amount() >= out_amount);
+            }
+        }
+    }
+    assert!(split_out_amount([0, 100, 0]).is_err());
+
+    let instruction = RouteBuilder::new(Pubkey::new_unique(), 50, 0)
+        .build(&split.plan(), in_amount)
+        .unwrap();
+    let route = Route::try_from_instruction_data(&instruction.data).unwrap();
+    assert_eq!(
+        route.swap_leg.to_string(),
+        format!(
+            "Split\n  {}%\n    Balansol\n  {}%\n    Balansol\n  {}%\n    TokenSwap\n",
+            split.legs[0].percent, split.legs[1].percent, split.legs[2].percent
+        )
+    );
+    assert_eq!(route.quoted_out_amount, split.out_amount());
+
+    // A small amount is not worth splitting
+    let split = optimize_split(&amms, mint_a, mint_b, 1_000).unwrap();
+    assert_eq!(split.legs.len(), 1);
+    assert_eq!(split.legs[0].percent, 100);
+}
+
+#[test]
+fn test_optimize_split_errors() {
+    use crate::balansol_amm::{mock_keyed_account, mock_pool, BalansolAmm};
+
+    let pool = mock_pool(vec![1_000_000, 1_000_000], vec![1, 1]);
+    let amm = BalansolAmm::from_keyed_account(&mock_keyed_account(&pool)).unwrap();
+    let (mint_a, mint_b) = (pool.mints[0], pool.mints[1]);
+
+    assert!(optimize_split(&[], mint_a, mint_b, 1_000).is_err());
+    assert!(optimize_split(&[&amm], mint_a, mint_a, 1_000).is_err());
+    assert!(optimize_split(&[&amm], mint_a, Pubkey::new_unique(), 1_000).is_err());
+    assert!(optimize_split(&[&amm], mint_a, mint_b, 0).is_err());
+    // More than the max in ratio of the only pool
+    assert!(optimize_split(&[&amm], mint_a, mint_b, 900_000).is_err());
+    let split = optimize_split(&[&amm], mint_a, mint_b, 1_000).unwrap();
+    assert_eq!(split.legs.len(), 1);
+}
@@ -0,0 +1,560 @@
+use anchor_lang::{
+    prelude::AccountInfo, solana_program::entrypoint::ProgramResult, AccountDeserialize,
+    AccountSerialize, InstructionData,
+};
+use anchor_spl::associated_token::get_associated_token_address;
+use jupiter_core::{
+    amm::{Amm, KeyedAccount, QuoteParams, SwapMode, SwapParams},
+    balansol::{
+        apply_swap_events, parse_swap_events, program::instruction, BalansolAmm, ErrorCode,
+        MintActionState, Pool, PoolState,
+    },
+};
+use solana_program_runtime::{log_collector::LogCollector, stable_log};
+use solana_program_test::{
+    builtin_process_instruction, processor, BanksClientError, InvokeContext, ProgramTest,
+    ProgramTestContext,
+};
+use solana_sdk::{
+    account::Account,
+    account_info::AccountInfo as SdkAccountInfo,
+    instruction::{Instruction, InstructionError},
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    signature::Signer,
+    transaction::{Transaction, TransactionError},
+};
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+    sync::Once,
+};
+
+// Anchor's entry ties the accounts slice to the account lifetimes
+fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    jupiter_core::balansol::program::entry(program_id, accounts, data)
+}
+
+thread_local! {
+    // Logs of the transaction the Balansol program runs in. A strong reference
+    // would keep the bank from taking the logs back once the transaction ends
+    static LOG_COLLECTOR: RefCell<Weak<RefCell<LogCollector>>> = const { RefCell::new(Weak::new()) };
+}
+
+fn process_balansol_instruction(
+    first_instruction_account: usize,
+    invoke_context: &mut InvokeContext,
+) -> Result<(), InstructionError> {
+    LOG_COLLECTOR.with(|log_collector| {
+        *log_collector.borrow_mut() = invoke_context
+            .get_log_collector()
+            .as_ref()
+            .map(Rc::downgrade)
+            .unwrap_or_default();
+    });
+    builtin_process_instruction(
+        process_instruction,
+        first_instruction_account,
+        invoke_context,
+    )
+}
+
+struct DefaultSyscallStubs;
+impl SyscallStubs for DefaultSyscallStubs {}
+
+// The native syscall stubs of solana-program-test 1.14 print `sol_log_data`
+// to stdout. These write it to the transaction logs as the BPF runtime does,
+// so that the `SwapEvent` of `emit!` can be read back.
+struct LogDataSyscallStubs {
+    stubs: Box<dyn SyscallStubs>,
+}
+
+impl SyscallStubs for LogDataSyscallStubs {
+    fn sol_log(&self, message: &str) {
+        self.stubs.sol_log(message)
+    }
+    fn sol_log_compute_units(&self) {
+        self.stubs.sol_log_compute_units()
+    }
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[SdkAccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> Result<(), ProgramError> {
+        self.stubs
+            .sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.stubs.sol_get_clock_sysvar(var_addr)
+    }
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.stubs.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.stubs.sol_get_fees_sysvar(var_addr)
+    }
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.stubs.sol_get_rent_sysvar(var_addr)
+    }
+    unsafe fn sol_memcpy(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.stubs.sol_memcpy(dst, src, n)
+    }
+    unsafe fn sol_memmove(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.stubs.sol_memmove(dst, src, n)
+    }
+    unsafe fn sol_memcmp(&self, s1: *const u8, s2: *const u8, n: usize, result: *mut i32) {
+        self.stubs.sol_memcmp(s1, s2, n, result)
+    }
+    unsafe fn sol_memset(&self, s: *mut u8, c: u8, n: usize) {
+        self.stubs.sol_memset(s, c, n)
+    }
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.stubs.sol_get_return_data()
+    }
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.stubs.sol_set_return_data(data)
+    }
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        LOG_COLLECTOR.with(|log_collector| {
+            stable_log::program_data(&log_collector.borrow().upgrade(), fields);
+        });
+    }
+    fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+        self.stubs.sol_get_processed_sibling_instruction(index)
+    }
+    fn sol_get_stack_height(&self) -> u64 {
+        self.stubs.sol_get_stack_height()
+    }
+}
+
+fn mint_account() -> Account {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        mint_authority: COption::None,
+        supply: u64::MAX / 2,
+        decimals: 6,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    }
+    .pack_into_slice(&mut data);
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(owner: &Pubkey, mint: &Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..spl_token::state::Account::default()
+    }
+    .pack_into_slice(&mut data);
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn pool_account(pool: &Pool) -> Account {
+    let mut data = vec![];
+    pool.try_serialize(&mut data).unwrap();
+    data.resize(Pool::LEN, 0);
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: jupiter_core::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// The swap instruction of this crate, run natively. It prices swaps with the
+// same fixed-point math as `quote`, so it checks that the instruction and the
+// quote agree, not that they agree with the deployed program.
+fn crate_program_test() -> ProgramTest {
+    ProgramTest::new(
+        "balansol",
+        jupiter_core::ID,
+        Some(process_balansol_instruction),
+    )
+}
+
+// The program deployed at 6SRa2K..., which still prices swaps with f64 math.
+// Its binary is not checked in, dump it into tests/fixtures with
+// `solana program dump 6SRa2Kc3G4wTG319G4Se6yrRWeS1A1Hj79BC3o7X9v6T tests/fixtures/balansol_deployed.so`
+fn deployed_program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::default();
+    program_test.prefer_bpf(true);
+    program_test.add_program("balansol_deployed", jupiter_core::ID, None);
+    program_test.prefer_bpf(false);
+    program_test
+}
+
+// Starts a bank with a funded pool, the payer holds `bid_balance` of the first mint
+async fn start_with_pool(
+    mut program_test: ProgramTest,
+    reserves: Vec<u64>,
+    weights: Vec<u64>,
+    bid_balance: u64,
+) -> (ProgramTestContext, Pubkey, Pool) {
+    // Run the token programs natively, like the program under test
+    program_test.add_program(
+        "spl_token",
+        spl_token::ID,
+        processor!(spl_token::processor::Processor::process),
+    );
+    program_test.add_program(
+        "spl_associated_token_account",
+        spl_associated_token_account::ID,
+        processor!(spl_associated_token_account::processor::process_instruction),
+    );
+
+    let key = Pubkey::new_unique();
+    let (treasurer, _) =
+        Pubkey::find_program_address(&[b"treasurer", &key.to_bytes()], &jupiter_core::ID);
+    let mints: Vec<Pubkey> = reserves.iter().map(|_| Pubkey::new_unique()).collect();
+    let mut treasuries = vec![];
+    for (mint, reserve) in mints.iter().zip(&reserves) {
+        let treasury = get_associated_token_address(&treasurer, mint);
+        program_test.add_account(*mint, mint_account());
+        program_test.add_account(treasury, token_account(&treasurer, mint, *reserve));
+        treasuries.push(treasury);
+    }
+    let pool = Pool {
+        authority: Pubkey::new_unique(),
+        fee: 2_500_000, // 0.25%
+        tax: 500_000,   // 0.05%
+        state: PoolState::Initialized,
+        mint_lpt: Pubkey::new_unique(),
+        taxman: Pubkey::new_unique(),
+        actions: mints.iter().map(|_| MintActionState::Active).collect(),
+        treasuries,
+        mints,
+        reserves,
+        weights,
+    };
+    program_test.add_account(key, pool_account(&pool));
+
+    let mut context = program_test.start_with_context().await;
+    // Wraps the stubs solana-program-test installs when starting
+    static LOG_DATA_STUBS: Once = Once::new();
+    LOG_DATA_STUBS.call_once(|| {
+        let stubs = set_syscall_stubs(Box::new(DefaultSyscallStubs));
+        set_syscall_stubs(Box::new(LogDataSyscallStubs { stubs }));
+    });
+    let user = context.payer.pubkey();
+    context.set_account(
+        &get_associated_token_address(&user, &pool.mints[0]),
+        &token_account(&user, &pool.mints[0], bid_balance).into(),
+    );
+    (context, key, pool)
+}
+
+async fn get_pool(context: &mut ProgramTestContext, key: Pubkey) -> (BalansolAmm, Pool) {
+    let account = context
+        .banks_client
+        .get_account(key)
+        .await
+        .unwrap()
+        .unwrap();
+    let pool = Pool::try_deserialize(&mut account.data.as_slice()).unwrap();
+    let amm = BalansolAmm::from_keyed_account(&KeyedAccount {
+        key,
+        account,
+        params: None,
+    })
+    .unwrap();
+    (amm, pool)
+}
+
+async fn get_token_balance(context: &mut ProgramTestContext, token_account: Pubkey) -> u64 {
+    match context
+        .banks_client
+        .get_account(token_account)
+        .await
+        .unwrap()
+    {
+        Some(account) => {
+            spl_token::state::Account::unpack(&account.data)
+                .unwrap()
+                .amount
+        }
+        None => 0,
+    }
+}
+
+async fn swap_transaction(
+    context: &mut ProgramTestContext,
+    amm: &BalansolAmm,
+    bid_mint: Pubkey,
+    ask_mint: Pubkey,
+    bid_amount: u64,
+    limit: u64,
+) -> Transaction {
+    let user = context.payer.pubkey();
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: bid_mint,
+            destination_mint: ask_mint,
+            user_source_token_account: get_associated_token_address(&user, &bid_mint),
+            user_destination_token_account: get_associated_token_address(&user, &ask_mint),
+            user_transfer_authority: user,
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: bid_amount,
+        })
+        .unwrap();
+    // Jupiter passes the Balansol program first, the instruction takes the rest
+    let instruction = Instruction {
+        program_id: jupiter_core::ID,
+        accounts: swap_leg_and_account_metas.account_metas[1..].to_vec(),
+        data: instruction::Swap { bid_amount, limit }.data(),
+    };
+    let recent_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&user),
+        &[&context.payer],
+        recent_blockhash,
+    )
+}
+
+async fn swap(
+    context: &mut ProgramTestContext,
+    amm: &BalansolAmm,
+    bid_mint: Pubkey,
+    ask_mint: Pubkey,
+    bid_amount: u64,
+    limit: u64,
+) -> Result<(), BanksClientError> {
+    let transaction = swap_transaction(context, amm, bid_mint, ask_mint, bid_amount, limit).await;
+    context.banks_client.process_transaction(transaction).await
+}
+
+fn assert_anchor_error(result: Result<(), BanksClientError>, error_code: ErrorCode) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(0, InstructionError::Custom(code)) => {
+            assert_eq!(code, u32::from(error_code))
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+// Swaps with the quoted amount as limit, the program must pay at least the
+// quote. When `exact`, it pays the quote and the pool follows `simulate_and_apply`
+async fn assert_swaps_match_quote(program_test: ProgramTest, exact: bool) {
+    let (mut context, key, pool) = start_with_pool(
+        program_test,
+        vec![25_000_000_000, 500_000_000_000, 8_000_000_000],
+        vec![500_000_000, 300_000_000, 200_000_000],
+        10_000_000_000,
+    )
+    .await;
+    let user = context.payer.pubkey();
+    let (bid_mint, ask_mint) = (pool.mints[0], pool.mints[1]);
+    let user_ask_account = get_associated_token_address(&user, &ask_mint);
+    let taxman_account = get_associated_token_address(&pool.taxman, &ask_mint);
+
+    let quote_params = |amount| QuoteParams {
+        amount,
+        input_mint: bid_mint,
+        output_mint: ask_mint,
+        swap_mode: SwapMode::ExactIn,
+    };
+    let mut expected_reserves = pool.reserves.clone();
+    // Follows the pool locally, without fetching it after the swaps
+    let (mut simulated_amm, _) = get_pool(&mut context, key).await;
+    for bid_amount in [1_000, 1_000_000, 3_000_000_000] {
+        let (amm, _) = get_pool(&mut context, key).await;
+        let quote = amm.quote(&quote_params(bid_amount)).unwrap();
+        let simulated_quote = simulated_amm
+            .simulate_and_apply(&quote_params(bid_amount))
+            .unwrap();
+        if exact {
+            assert_eq!(simulated_quote.out_amount, quote.out_amount);
+        }
+        let balance_before = get_token_balance(&mut context, user_ask_account).await;
+        let tax_before = get_token_balance(&mut context, taxman_account).await;
+
+        swap(
+            &mut context,
+            &amm,
+            bid_mint,
+            ask_mint,
+            bid_amount,
+            quote.out_amount,
+        )
+        .await
+        .unwrap();
+
+        let received = get_token_balance(&mut context, user_ask_account).await - balance_before;
+        let tax = get_token_balance(&mut context, taxman_account).await - tax_before;
+        assert!(received >= quote.out_amount);
+
+        // The fee stays in the bid reserve, the tax leaves the ask reserve
+        expected_reserves[0] += bid_amount;
+        expected_reserves[1] -= received + tax;
+        let (amm_after, pool_after) = get_pool(&mut context, key).await;
+        assert_eq!(pool_after.reserves, expected_reserves);
+        if exact {
+            assert_eq!(received, quote.out_amount);
+            assert_eq!(
+                simulated_amm
+                    .quote(&quote_params(1_000_000))
+                    .unwrap()
+                    .out_amount,
+                amm_after
+                    .quote(&quote_params(1_000_000))
+                    .unwrap()
+                    .out_amount
+            );
+        }
+        for (treasury, reserve) in pool.treasuries.iter().zip(&expected_reserves) {
+            assert_eq!(get_token_balance(&mut context, *treasury).await, *reserve);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_swap_matches_quote() {
+    assert_swaps_match_quote(crate_program_test(), true).await;
+}
+
+// Ignored as the deployed binary is not checked in, see `deployed_program_test`
+#[tokio::test]
+#[ignore]
+async fn test_deployed_swap_matches_quote() {
+    assert_swaps_match_quote(deployed_program_test(), false).await;
+}
+
+#[tokio::test]
+async fn test_swap_errors() {
+    let (mut context, key, mut pool) = start_with_pool(
+        crate_program_test(),
+        vec![25_000_000_000, 500_000_000_000],
+        vec![500_000_000, 500_000_000],
+        10_000_000_000,
+    )
+    .await;
+    let (amm, _) = get_pool(&mut context, key).await;
+    let (bid_mint, ask_mint) = (pool.mints[0], pool.mints[1]);
+    let quote = amm
+        .quote(&QuoteParams {
+            amount: 1_000_000,
+            input_mint: bid_mint,
+            output_mint: ask_mint,
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+
+    let result = swap(
+        &mut context,
+        &amm,
+        bid_mint,
+        ask_mint,
+        1_000_000,
+        quote.out_amount + 1,
+    )
+    .await;
+    assert_anchor_error(result, ErrorCode::Slippage);
+
+    let result = swap(&mut context, &amm, bid_mint, ask_mint, 0, 0).await;
+    assert_anchor_error(result, ErrorCode::ParamsZero);
+
+    // The ask mint can only be sold to the pool
+    pool.actions[1] = MintActionState::BidOnly;
+    context.set_account(&key, &pool_account(&pool).into());
+    let result = swap(&mut context, &amm, bid_mint, ask_mint, 1_000_000, 0).await;
+    assert_anchor_error(result, ErrorCode::MintState);
+
+    pool.actions[1] = MintActionState::Active;
+    pool.state = PoolState::Frozen;
+    context.set_account(&key, &pool_account(&pool).into());
+    let result = swap(&mut context, &amm, bid_mint, ask_mint, 1_000_000, 0).await;
+    assert_anchor_error(result, ErrorCode::PoolStopped);
+}
+
+#[tokio::test]
+async fn test_swap_events_replay() {
+    let (mut context, key, pool) = start_with_pool(
+        crate_program_test(),
+        vec![25_000_000_000, 500_000_000_000, 8_000_000_000],
+        vec![500_000_000, 300_000_000, 200_000_000],
+        10_000_000_000,
+    )
+    .await;
+    let user = context.payer.pubkey();
+    let mut cached_pool = get_pool(&mut context, key).await.1;
+
+    // Sell the first mint, then sell back part of what was received
+    for (bid_idx, ask_idx) in [(0, 1), (0, 2), (1, 0)] {
+        let (amm, _) = get_pool(&mut context, key).await;
+        let (bid_mint, ask_mint) = (pool.mints[bid_idx], pool.mints[ask_idx]);
+        let user_bid_account = get_associated_token_address(&user, &bid_mint);
+        let user_ask_account = get_associated_token_address(&user, &ask_mint);
+        let taxman_account = get_associated_token_address(&pool.taxman, &ask_mint);
+        let bid_amount = get_token_balance(&mut context, user_bid_account).await / 3;
+        let balance_before = get_token_balance(&mut context, user_ask_account).await;
+        let tax_before = get_token_balance(&mut context, taxman_account).await;
+        let transaction =
+            swap_transaction(&mut context, &amm, bid_mint, ask_mint, bid_amount, 0).await;
+        // The same transaction, simulated first for its logs
+        let logs = context
+            .banks_client
+            .simulate_transaction(transaction.clone())
+            .await
+            .unwrap()
+            .simulation_details
+            .unwrap()
+            .logs;
+        context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap();
+
+        let events = parse_swap_events(&jupiter_core::ID, &logs);
+        assert_eq!(events.len(), 1, "{:#?}", logs);
+        let event = &events[0];
+        assert_eq!(
+            (event.authority, event.pool, event.bid_mint, event.ask_mint),
+            (user, key, bid_mint, ask_mint)
+        );
+        assert_eq!((event.bid_amount, event.limit), (bid_amount, 0));
+        assert_eq!(
+            event.ask_amount,
+            get_token_balance(&mut context, user_ask_account).await - balance_before
+        );
+        assert_eq!(
+            event.total_tax_fee_amount,
+            get_token_balance(&mut context, taxman_account).await - tax_before
+        );
+        assert_eq!(apply_swap_events(&key, &mut cached_pool, &events), Some(1));
+    }
+
+    let (_, fetched_pool) = get_pool(&mut context, key).await;
+    let (mut cached_data, mut fetched_data) = (vec![], vec![]);
+    cached_pool.try_serialize(&mut cached_data).unwrap();
+    fetched_pool.try_serialize(&mut fetched_data).unwrap();
+    assert_eq!(cached_data, fetched_data);
+    assert_ne!(fetched_pool.reserves, pool.reserves);
+}
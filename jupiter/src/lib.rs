@@ -9,10 +9,11 @@ anchor_lang::declare_id!("JUP4Fb2cqiRUcaTHdrPC8h2gNsA2ETXiPDD33WcGuJB");
 pub mod jupiter_override {
     use super::Side;
     use anchor_lang::{prelude::*, Discriminator};
-    use anchor_lang::{AnchorSerialize, InstructionData};
-    use std::io::Write;
+    use anchor_lang::{AnchorDeserialize, AnchorSerialize, InstructionData};
+    use std::fmt;
+    use std::io::{Error, ErrorKind, Write};
 
-    #[derive(AnchorSerialize)]
+    #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
     pub enum Swap {
         Saber,
         SaberAddDecimalsDeposit,
@@ -44,12 +45,13 @@ pub mod jupiter_override {
         Openbook { side: Side },
     }
 
-    #[derive(AnchorSerialize)]
+    #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
     pub struct SplitLeg {
         pub percent: u8,
         pub swap_leg: SwapLeg,
     }
 
+    #[derive(Clone, Debug)]
     pub enum SwapLeg {
         Chain { swap_legs: Vec<SwapLeg> },
         Split { split_legs: Vec<SplitLeg> },
@@ -76,7 +78,63 @@ pub mod jupiter_override {
         }
     }
 
-    #[derive(AnchorSerialize)]
+    impl AnchorDeserialize for SwapLeg {
+        #[inline]
+        fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+            match u8::deserialize(buf)? {
+                0 => Ok(SwapLeg::Chain {
+                    swap_legs: AnchorDeserialize::deserialize(buf)?,
+                }),
+                1 => Ok(SwapLeg::Split {
+                    split_legs: AnchorDeserialize::deserialize(buf)?,
+                }),
+                2 => Ok(SwapLeg::Swap {
+                    swap: AnchorDeserialize::deserialize(buf)?,
+                }),
+                tag => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unexpected SwapLeg variant {}", tag),
+                )),
+            }
+        }
+    }
+
+    impl SwapLeg {
+        fn fmt_indented(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+            match self {
+                SwapLeg::Chain { swap_legs } => {
+                    writeln!(f, "{:indent$}Chain", "", indent = indent)?;
+                    for swap_leg in swap_legs {
+                        swap_leg.fmt_indented(f, indent + 2)?;
+                    }
+                    Ok(())
+                }
+                SwapLeg::Split { split_legs } => {
+                    writeln!(f, "{:indent$}Split", "", indent = indent)?;
+                    for split_leg in split_legs {
+                        writeln!(
+                            f,
+                            "{:indent$}{}%",
+                            "",
+                            split_leg.percent,
+                            indent = indent + 2
+                        )?;
+                        split_leg.swap_leg.fmt_indented(f, indent + 4)?;
+                    }
+                    Ok(())
+                }
+                SwapLeg::Swap { swap } => writeln!(f, "{:indent$}{:?}", "", swap, indent = indent),
+            }
+        }
+    }
+
+    impl fmt::Display for SwapLeg {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.fmt_indented(f, 0)
+        }
+    }
+
+    #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
     pub struct Route {
         pub swap_leg: SwapLeg,
         pub in_amount: u64,
@@ -89,4 +147,178 @@ pub mod jupiter_override {
     }
 
     impl InstructionData for Route {}
+
+    impl Route {
+        /// Decodes the data of a `route` instruction, discriminator included
+        pub fn try_from_instruction_data(data: &[u8]) -> std::io::Result<Self> {
+            if data.len() < 8 || data[..8] != Self::DISCRIMINATOR {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Not a route instruction",
+                ));
+            }
+            Self::try_from_slice(&data[8..])
+        }
+    }
+
+    impl fmt::Display for Route {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            writeln!(
+                f,
+                "Route in_amount: {}, quoted_out_amount: {}, slippage_bps: {}, platform_fee_bps: {}",
+                self.in_amount, self.quoted_out_amount, self.slippage_bps, self.platform_fee_bps
+            )?;
+            self.swap_leg.fmt_indented(f, 2)
+        }
+    }
+
+    #[cfg(test)]
+    fn random_swap(rng: &mut impl rand::Rng) -> Swap {
+        let side = if rng.gen() { Side::Bid } else { Side::Ask };
+        match rng.gen_range(0..28) {
+            0 => Swap::Saber,
+            1 => Swap::SaberAddDecimalsDeposit,
+            2 => Swap::SaberAddDecimalsWithdraw,
+            3 => Swap::TokenSwap,
+            4 => Swap::Sencha,
+            5 => Swap::Step,
+            6 => Swap::Cropper,
+            7 => Swap::Raydium,
+            8 => Swap::Crema,
+            9 => Swap::Lifinity,
+            10 => Swap::Mercurial,
+            11 => Swap::Cykura,
+            12 => Swap::Serum { side },
+            13 => Swap::MarinadeDeposit,
+            14 => Swap::MarinadeUnstake,
+            15 => Swap::Aldrin { side },
+            16 => Swap::AldrinV2 { side },
+            17 => Swap::Whirlpool { a_to_b: rng.gen() },
+            18 => Swap::Invariant { x_to_y: rng.gen() },
+            19 => Swap::Meteora,
+            20 => Swap::GooseFX,
+            21 => Swap::DeltaFi { stable: rng.gen() },
+            22 => Swap::Balansol,
+            23 => Swap::MarcoPolo { x_to_y: rng.gen() },
+            24 => Swap::Dradex { side },
+            25 => Swap::LifinityV2,
+            26 => Swap::RaydiumClmm,
+            _ => Swap::Openbook { side },
+        }
+    }
+
+    #[cfg(test)]
+    fn random_swap_leg(rng: &mut impl rand::Rng, depth: usize) -> SwapLeg {
+        let leg_count = rng.gen_range(1..4);
+        match rng.gen_range(0..3) {
+            0 if depth > 1 => SwapLeg::Chain {
+                swap_legs: (0..leg_count)
+                    .map(|_| random_swap_leg(rng, depth - 1))
+                    .collect(),
+            },
+            1 if depth > 1 => SwapLeg::Split {
+                split_legs: (0..leg_count)
+                    .map(|_| SplitLeg {
+                        percent: rng.gen_range(0..=100),
+                        swap_leg: random_swap_leg(rng, depth - 1),
+                    })
+                    .collect(),
+            },
+            _ => SwapLeg::Swap {
+                swap: random_swap(rng),
+            },
+        }
+    }
+
+    #[test]
+    fn test_route_round_trip() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(8);
+        for _ in 0..1_000 {
+            let route = Route {
+                swap_leg: random_swap_leg(&mut rng, 3),
+                in_amount: rand::Rng::gen(&mut rng),
+                quoted_out_amount: rand::Rng::gen(&mut rng),
+                slippage_bps: rand::Rng::gen(&mut rng),
+                platform_fee_bps: rand::Rng::gen(&mut rng),
+            };
+            let data = route.data();
+            let decoded = Route::try_from_instruction_data(&data).unwrap();
+            assert_eq!(decoded.data(), data);
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", route));
+            assert_eq!(decoded.to_string(), route.to_string());
+        }
+    }
+
+    #[test]
+    fn test_route_decode_errors() {
+        let route = Route {
+            swap_leg: SwapLeg::Swap {
+                swap: Swap::Balansol,
+            },
+            in_amount: 1_000,
+            quoted_out_amount: 990,
+            slippage_bps: 50,
+            platform_fee_bps: 0,
+        };
+        let data = route.data();
+        // Missing discriminator, truncated and trailing data
+        assert!(Route::try_from_instruction_data(&data[8..]).is_err());
+        assert!(Route::try_from_instruction_data(&data[..data.len() - 1]).is_err());
+        assert!(Route::try_from_instruction_data(&[data.clone(), vec![0]].concat()).is_err());
+        // Unknown SwapLeg tag
+        let mut bad_tag = data.clone();
+        bad_tag[8] = 3;
+        assert!(Route::try_from_instruction_data(&bad_tag).is_err());
+        // Unknown Swap variant
+        let mut bad_swap = data;
+        bad_swap[9] = 28;
+        assert!(Route::try_from_instruction_data(&bad_swap).is_err());
+    }
+
+    #[test]
+    fn test_route_display() {
+        let route = Route {
+            swap_leg: SwapLeg::Chain {
+                swap_legs: vec![
+                    SwapLeg::Swap {
+                        swap: Swap::Whirlpool { a_to_b: true },
+                    },
+                    SwapLeg::Split {
+                        split_legs: vec![
+                            SplitLeg {
+                                percent: 60,
+                                swap_leg: SwapLeg::Swap {
+                                    swap: Swap::Balansol,
+                                },
+                            },
+                            SplitLeg {
+                                percent: 40,
+                                swap_leg: SwapLeg::Swap {
+                                    swap: Swap::Serum { side: Side::Bid },
+                                },
+                            },
+                        ],
+                    },
+                ],
+            },
+            in_amount: 1_000,
+            quoted_out_amount: 990,
+            slippage_bps: 50,
+            platform_fee_bps: 0,
+        };
+        assert_eq!(
+            route.to_string(),
+            "Route in_amount: 1000, quoted_out_amount: 990, slippage_bps: 50, platform_fee_bps: 0
+  Chain
+    Whirlpool { a_to_b: true }
+    Split
+      60%
+        Balansol
+      40%
+        Serum { side: Bid }
+"
+        );
+    }
 }